@@ -0,0 +1,568 @@
+use crate::karabiner_data::{
+    Condition, ConditionType, FromEvent, Manipulator, ModifierKey, To, VirtualKey,
+};
+
+/// Where a manipulator comes from: its rule set and its position within it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub rule_set: String,
+    /// Zero-based index among the manipulators of the rule set.
+    pub index: usize,
+    /// The trigger of the manipulator, e.g. `vk4 + j (frontmost: com.microsoft.VSCode)`.
+    pub trigger: String,
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} #{} [{}]", self.rule_set, self.index, self.trigger)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConflictKind {
+    /// Same from event, modifiers and conditions; Karabiner always uses the first one.
+    Duplicate,
+    /// Some key presses match both, but neither is a special case of the other, so which one
+    /// fires depends on their order.
+    Overlap,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub kind: ConflictKind,
+    pub first: Location,
+    pub second: Location,
+}
+
+impl std::fmt::Display for Conflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            ConflictKind::Duplicate => write!(f, "duplicate binding: "),
+            ConflictKind::Overlap => write!(f, "overlapping bindings: "),
+        }?;
+        write!(f, "{} and {}", self.first, self.second)
+    }
+}
+
+/// Pairs of manipulators across (and within) rule sets that bind the same trigger.
+///
+/// A manipulator whose trigger is a special case of an earlier or later one (e.g. an app-specific
+/// `vk1 + w` next to a global `vk1 + w`) is an intended override and is not reported here.
+/// Variables set by virtual keys act as layers, so manipulators that require different
+/// variables (`vk1 + w` and `vk2 + w`) are not reported either, although both keys could be held.
+pub fn conflicts(rule_sets: &[(String, Vec<Manipulator>)]) -> Vec<Conflict> {
+    let triggers = triggers(rule_sets);
+    let mut conflicts = vec![];
+    for (i, (first, a)) in triggers.iter().enumerate() {
+        for (second, b) in &triggers[i + 1..] {
+            let kind = if a.same_as(b) {
+                ConflictKind::Duplicate
+            } else if same_set(&a.layer(), &b.layer())
+                && a.overlaps(b)
+                && !a.within(b)
+                && !b.within(a)
+            {
+                ConflictKind::Overlap
+            } else {
+                continue;
+            };
+            conflicts.push(Conflict {
+                kind,
+                first: first.clone(),
+                second: second.clone(),
+            });
+        }
+    }
+    conflicts
+}
+
+/// A manipulator that can never fire because an earlier one matches every key press it matches.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Shadowed {
+    pub shadowed: Location,
+    pub by: Location,
+}
+
+impl std::fmt::Display for Shadowed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "unreachable manipulator: {} is shadowed by the earlier {}",
+            self.shadowed, self.by
+        )
+    }
+}
+
+/// Manipulators that never fire because Karabiner, which evaluates manipulators in order and uses
+/// the first match, always picks an earlier, more general one: e.g. a global `vk1 + w` before an
+/// iTerm2-only `vk1 + w`, or an `Optional(Any)` mapping before a `Mandatory(Shift)` one.
+/// `rule_sets` must be in evaluation order. Exact duplicates are left to `conflicts`.
+pub fn shadowed(rule_sets: &[(String, Vec<Manipulator>)]) -> Vec<Shadowed> {
+    let triggers = triggers(rule_sets);
+    triggers
+        .iter()
+        .enumerate()
+        .filter_map(|(i, (location, trigger))| {
+            let (by, _) = triggers[..i]
+                .iter()
+                .find(|(_, earlier)| trigger.within(earlier) && !trigger.same_as(earlier))?;
+            Some(Shadowed {
+                shadowed: location.clone(),
+                by: by.clone(),
+            })
+        })
+        .collect()
+}
+
+/// How narrowly a manipulator's trigger is scoped. Compared field by field, so an app condition
+/// outweighs any number of variable conditions, which outweigh mandatory modifiers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Specificity {
+    pub app_conditions: usize,
+    pub variable_conditions: usize,
+    pub mandatory_modifiers: usize,
+    /// Whether other modifiers must not be held, i.e. `optional` does not contain `any`.
+    pub exact_modifiers: bool,
+}
+
+pub fn specificity(manipulator: &Manipulator) -> Specificity {
+    let trigger = Trigger::new(manipulator);
+    let count = |f: fn(&Condition) -> bool| trigger.conditions.iter().filter(|c| f(c)).count();
+    Specificity {
+        app_conditions: count(|c| matches!(c, Condition::OnApplication { .. })),
+        variable_conditions: count(|c| matches!(c, Condition::WithVirtualKey { .. })),
+        mandatory_modifiers: trigger.mandatory.len(),
+        exact_modifiers: trigger.optional.is_some(),
+    }
+}
+
+/// Sorts manipulators so that more specific ones come first and can no longer be shadowed by more
+/// general ones. Equally specific manipulators keep their relative order.
+pub fn sort_by_specificity(manipulators: &mut [Manipulator]) {
+    manipulators.sort_by_key(|manipulator| std::cmp::Reverse(specificity(manipulator)));
+}
+
+/// The location of each manipulator, in order.
+pub fn locations(rule_sets: &[(String, Vec<Manipulator>)]) -> Vec<Location> {
+    triggers(rule_sets)
+        .into_iter()
+        .map(|(location, _)| location)
+        .collect()
+}
+
+fn triggers(rule_sets: &[(String, Vec<Manipulator>)]) -> Vec<(Location, Trigger<'_>)> {
+    rule_sets
+        .iter()
+        .flat_map(|(name, manipulators)| {
+            manipulators.iter().enumerate().map(|(index, manipulator)| {
+                let trigger = Trigger::new(manipulator);
+                let location = Location {
+                    rule_set: name.clone(),
+                    index,
+                    trigger: trigger.to_string(),
+                };
+                (location, trigger)
+            })
+        })
+        .collect()
+}
+
+/// The key presses a manipulator reacts to. Left and right modifiers are treated as the generic
+/// modifier, which is close enough for the bindings karaconf generates.
+struct Trigger<'a> {
+    event: Option<&'a FromEvent>,
+    mandatory: Vec<ModifierKey>,
+    /// `None` when any other modifier may be held.
+    optional: Option<Vec<ModifierKey>>,
+    conditions: Vec<&'a Condition>,
+}
+
+impl<'a> Trigger<'a> {
+    fn new(manipulator: &'a Manipulator) -> Self {
+        let (mandatory, optional) = match &manipulator.from.modifiers {
+            Some(modifiers) => (modifiers.mandatory(), modifiers.optional()),
+            None => (&[][..], &[][..]),
+        };
+        let optional = if optional.contains(&ModifierKey::Any) {
+            None
+        } else {
+            Some(generic_set(optional))
+        };
+        Trigger {
+            event: manipulator.from.event.as_ref(),
+            mandatory: generic_set(mandatory),
+            optional,
+            conditions: manipulator.conditions.iter().flatten().collect(),
+        }
+    }
+
+    fn same_as(&self, other: &Trigger) -> bool {
+        self.event == other.event
+            && same_set(&self.mandatory, &other.mandatory)
+            && match (&self.optional, &other.optional) {
+                (None, None) => true,
+                (Some(a), Some(b)) => same_set(a, b),
+                _ => false,
+            }
+            && same_set(&self.conditions, &other.conditions)
+    }
+
+    /// The variables that must be set, e.g. `[vk1]` for a manipulator of the vk1 layer.
+    fn layer(&self) -> Vec<&VirtualKey> {
+        self.conditions
+            .iter()
+            .filter_map(|condition| match condition {
+                Condition::WithVirtualKey {
+                    r#type: ConditionType::VariableIf,
+                    name,
+                    ..
+                } => Some(name),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Whether some key press matches both.
+    fn overlaps(&self, other: &Trigger) -> bool {
+        self.event == other.event
+            && !self
+                .conditions
+                .iter()
+                .any(|a| other.conditions.iter().any(|b| exclusive(a, b)))
+            && other.allows(&self.mandatory)
+            && self.allows(&other.mandatory)
+    }
+
+    /// Whether every key press matching `self` also matches `other`.
+    fn within(&self, other: &Trigger) -> bool {
+        self.event == other.event
+            && other
+                .conditions
+                .iter()
+                .all(|b| self.conditions.iter().any(|a| implies(a, b)))
+            && other.mandatory.iter().all(|m| self.mandatory.contains(m))
+            && match (&self.optional, &other.optional) {
+                (_, None) => true,
+                (None, Some(_)) => false,
+                (Some(optional), Some(_)) => {
+                    other.allows(optional) && other.allows(&self.mandatory)
+                }
+            }
+    }
+
+    /// Whether holding `modifiers` on top of the mandatory ones still matches.
+    fn allows(&self, modifiers: &[ModifierKey]) -> bool {
+        modifiers.iter().all(|m| {
+            self.mandatory.contains(m)
+                || self
+                    .optional
+                    .as_ref()
+                    .is_none_or(|optional| optional.contains(m))
+        })
+    }
+}
+
+impl std::fmt::Display for Trigger<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parts = vec![];
+        for condition in &self.conditions {
+            if let Condition::WithVirtualKey {
+                r#type: ConditionType::VariableIf,
+                name,
+                value: 1,
+            } = condition
+            {
+                parts.push(name.as_str().to_string());
+            }
+        }
+        for modifier in &self.mandatory {
+            parts.push(json_name(modifier));
+        }
+        parts.push(match self.event {
+            Some(event) => json_name(event),
+            None => "(no from event)".to_string(),
+        });
+        write!(f, "{}", parts.join(" + "))?;
+        if self.optional.is_none() {
+            write!(f, " (any modifiers)")?;
+        }
+        for condition in &self.conditions {
+            match condition {
+                Condition::OnApplication {
+                    r#type,
+                    bundle_identifiers,
+                } => {
+                    let bundle_identifiers = bundle_identifiers
+                        .iter()
+                        .map(|b| b.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    match r#type {
+                        ConditionType::FrontmostApplicationUnless => {
+                            write!(f, " (frontmost: not {})", bundle_identifiers)?
+                        }
+                        _ => write!(f, " (frontmost: {})", bundle_identifiers)?,
+                    }
+                }
+                Condition::WithVirtualKey {
+                    r#type: ConditionType::VariableIf,
+                    value: 1,
+                    ..
+                } => {}
+                other => write!(f, " ({})", json_name(other))?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The JSON spelling of a value, e.g. `command` or `j`, or the JSON itself for objects.
+fn json_name<T: serde::Serialize>(value: &T) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(name)) => name,
+        Ok(serde_json::Value::Object(map)) if map.len() == 1 => match map.values().next() {
+            Some(serde_json::Value::String(name)) => name.clone(),
+            Some(value) => value.to_string(),
+            None => String::new(),
+        },
+        Ok(value) => value.to_string(),
+        Err(_) => String::new(),
+    }
+}
+
+fn generic(modifier: &ModifierKey) -> ModifierKey {
+    match modifier {
+        ModifierKey::LeftCommand
+        | ModifierKey::RightCommand
+        | ModifierKey::LeftGui
+        | ModifierKey::RightGui => ModifierKey::Cmd,
+        ModifierKey::LeftControl | ModifierKey::RightControl => ModifierKey::Ctrl,
+        ModifierKey::LeftOption
+        | ModifierKey::RightOption
+        | ModifierKey::LeftAlt
+        | ModifierKey::RightAlt => ModifierKey::Opt,
+        ModifierKey::LeftShift | ModifierKey::RightShift => ModifierKey::Shift,
+        other => other.clone(),
+    }
+}
+
+fn generic_set(modifiers: &[ModifierKey]) -> Vec<ModifierKey> {
+    let mut set: Vec<ModifierKey> = vec![];
+    for modifier in modifiers.iter().map(generic) {
+        if !set.contains(&modifier) {
+            set.push(modifier);
+        }
+    }
+    set
+}
+
+fn same_set<T: PartialEq>(a: &[T], b: &[T]) -> bool {
+    a.iter().all(|x| b.contains(x)) && b.iter().all(|x| a.contains(x))
+}
+
+/// Whether no state satisfies both conditions.
+fn exclusive(a: &Condition, b: &Condition) -> bool {
+    use ConditionType::*;
+    match (a, b) {
+        (
+            Condition::OnApplication {
+                r#type: type_a,
+                bundle_identifiers: a,
+            },
+            Condition::OnApplication {
+                r#type: type_b,
+                bundle_identifiers: b,
+            },
+        ) => match (type_a, type_b) {
+            (FrontmostApplicationIf, FrontmostApplicationIf) => {
+                !a.iter().any(|bundle| b.contains(bundle))
+            }
+            (FrontmostApplicationIf, FrontmostApplicationUnless) => {
+                a.iter().all(|bundle| b.contains(bundle))
+            }
+            (FrontmostApplicationUnless, FrontmostApplicationIf) => {
+                b.iter().all(|bundle| a.contains(bundle))
+            }
+            _ => false,
+        },
+        (
+            Condition::WithVirtualKey {
+                r#type: type_a,
+                name: name_a,
+                value: value_a,
+            },
+            Condition::WithVirtualKey {
+                r#type: type_b,
+                name: name_b,
+                value: value_b,
+            },
+        ) if name_a == name_b => match (type_a, type_b) {
+            (VariableIf, VariableIf) => value_a != value_b,
+            (VariableIf, VariableUnless) | (VariableUnless, VariableIf) => value_a == value_b,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Whether every state satisfying `a` satisfies `b`.
+fn implies(a: &Condition, b: &Condition) -> bool {
+    match (a, b) {
+        (
+            Condition::OnApplication {
+                r#type: ConditionType::FrontmostApplicationIf,
+                bundle_identifiers: a,
+            },
+            Condition::OnApplication {
+                r#type: ConditionType::FrontmostApplicationIf,
+                bundle_identifiers: b,
+            },
+        ) => a.iter().all(|bundle| b.contains(bundle)),
+        _ => a == b,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VariableProblem {
+    /// A condition reads the variable, but no manipulator sets it, so the condition never holds.
+    NeverSet,
+    /// The variable is set to a non-zero value and never back to zero, neither on key up nor by
+    /// another manipulator (as a toggle would), so it stays set.
+    NeverReleased,
+    /// The variable is set but no condition reads it.
+    NeverRead,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VariableIssue {
+    pub problem: VariableProblem,
+    pub variable: String,
+    /// The first manipulator that reads (for `NeverSet`) or sets the variable.
+    pub location: Location,
+}
+
+impl std::fmt::Display for VariableIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.problem {
+            VariableProblem::NeverSet => write!(
+                f,
+                "variable {:?} is used in a condition of {} but never set",
+                self.variable, self.location
+            ),
+            VariableProblem::NeverReleased => write!(
+                f,
+                "variable {:?} is set by {} but never released on key up or reset elsewhere",
+                self.variable, self.location
+            ),
+            VariableProblem::NeverRead => write!(
+                f,
+                "variable {:?} is set by {} but never used in a condition",
+                self.variable, self.location
+            ),
+        }
+    }
+}
+
+/// Checks that every variable used in a condition is set somewhere, that every variable that is
+/// set is released again (on key up or by a toggle), and that no variable is set but never read.
+pub fn variable_issues(rule_sets: &[(String, Vec<Manipulator>)]) -> Vec<VariableIssue> {
+    let mut read: Vec<(String, &Location)> = vec![];
+    // (variable, value, released on key up by the same manipulator, location)
+    let mut set: Vec<(String, serde_json::Value, bool, &Location)> = vec![];
+    let triggers = triggers(rule_sets);
+    let manipulators = rule_sets.iter().flat_map(|(_, manipulators)| manipulators);
+    for ((location, _), manipulator) in triggers.iter().zip(manipulators) {
+        for condition in manipulator.conditions.iter().flatten() {
+            if let Some(name) = condition_variable(condition) {
+                read.push((name, location));
+            }
+        }
+        let released = manipulator
+            .to_after_key_up
+            .iter()
+            .flatten()
+            .filter_map(set_variable)
+            .filter(|(_, value)| is_zero(value))
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+        let to = manipulator
+            .to
+            .iter()
+            .chain(manipulator.to_if_alone.iter().flatten());
+        for (name, value) in to.filter_map(set_variable) {
+            let released = released.contains(&name);
+            set.push((name, value, released, location));
+        }
+        for (name, value) in manipulator
+            .to_after_key_up
+            .iter()
+            .flatten()
+            .filter_map(set_variable)
+        {
+            set.push((name, value, true, location));
+        }
+    }
+
+    let mut issues = vec![];
+    let mut report = |problem, variable: &str, location: &Location| {
+        if !issues
+            .iter()
+            .any(|i: &VariableIssue| i.problem == problem && i.variable == variable)
+        {
+            issues.push(VariableIssue {
+                problem,
+                variable: variable.to_string(),
+                location: location.clone(),
+            });
+        }
+    };
+    for (name, location) in &read {
+        if !set.iter().any(|(set_name, ..)| set_name == name) {
+            report(VariableProblem::NeverSet, name, location);
+        }
+    }
+    for (name, value, released, location) in &set {
+        if !is_zero(value) && !released && !set.iter().any(|(n, v, _, _)| n == name && is_zero(v)) {
+            report(VariableProblem::NeverReleased, name, location);
+        }
+        if !read.iter().any(|(read_name, _)| read_name == name) {
+            report(VariableProblem::NeverRead, name, location);
+        }
+    }
+    issues
+}
+
+/// The variable a `variable_if`/`variable_unless` condition reads, including untyped ones such as
+/// a `variable_if` with a boolean value.
+fn condition_variable(condition: &Condition) -> Option<String> {
+    match condition {
+        Condition::WithVirtualKey { name, .. } => Some(name.as_str().to_string()),
+        Condition::OnApplication { .. } => None,
+        Condition::Other(value) => {
+            let r#type = value["type"].as_str()?;
+            (r#type == "variable_if" || r#type == "variable_unless")
+                .then(|| value["name"].as_str().map(str::to_string))?
+        }
+    }
+}
+
+fn set_variable(to: &To) -> Option<(String, serde_json::Value)> {
+    match to {
+        To::Variable { set_variable } => Some((
+            set_variable.name.as_str().to_string(),
+            serde_json::Value::from(set_variable.value),
+        )),
+        To::Other(value) => {
+            let set_variable = value.get("set_variable")?;
+            Some((
+                set_variable["name"].as_str()?.to_string(),
+                set_variable["value"].clone(),
+            ))
+        }
+        _ => None,
+    }
+}
+
+fn is_zero(value: &serde_json::Value) -> bool {
+    value.as_i64() == Some(0) || *value == serde_json::Value::Bool(false)
+}
@@ -0,0 +1,93 @@
+use crate::Error;
+
+/// Writes each file to a temporary sibling and renames the temporaries into place only after
+/// every one of them was written and synced, so an interrupted run never leaves a half-written
+/// file behind. If any write fails, the temporaries are removed and no target is touched. If a
+/// rename fails, the targets already replaced are put back from their previous contents and the
+/// remaining temporaries are removed; only a crash during the renames leaves some files replaced.
+///
+/// Replaced files keep their permissions. Symlinked targets (e.g. a karabiner.json kept in a
+/// dotfiles repository) are resolved so that the file they point to is replaced rather than the
+/// link itself.
+pub fn write_all(files: &[(&std::path::Path, &[u8])]) -> crate::Result<()> {
+    let mut renames: Vec<(std::path::PathBuf, std::path::PathBuf)> = vec![];
+    let result = files.iter().try_for_each(|(path, contents)| {
+        let target = match std::fs::canonicalize(path) {
+            Ok(target) => target,
+            Err(_) => path.to_path_buf(),
+        };
+        let temp = temp_path(&target)?;
+        renames.push((temp.clone(), target.clone()));
+        write_synced(&temp, contents, std::fs::metadata(&target).ok()).map_err(Error::io(&temp))
+    });
+    if let Err(e) = result {
+        for (temp, _) in &renames {
+            let _ = std::fs::remove_file(temp);
+        }
+        return Err(e);
+    }
+
+    // Read only now, so that the rollback restores what the renames replaced.
+    let previous = renames
+        .iter()
+        .map(|(_, target)| std::fs::read(target).ok())
+        .collect::<Vec<_>>();
+    for (i, (temp, target)) in renames.iter().enumerate() {
+        if let Err(source) = std::fs::rename(temp, target) {
+            for (temp, _) in &renames[i..] {
+                let _ = std::fs::remove_file(temp);
+            }
+            rollback(&renames[..i], &previous[..i]);
+            return Err(Error::io(target)(source));
+        }
+    }
+    Ok(())
+}
+
+/// Puts back the previous contents of targets that were already replaced, and removes targets
+/// that did not exist before. Best effort: the rename error is what gets reported.
+fn rollback(replaced: &[(std::path::PathBuf, std::path::PathBuf)], previous: &[Option<Vec<u8>>]) {
+    for ((temp, target), previous) in replaced.iter().zip(previous) {
+        match previous {
+            Some(contents) => {
+                let metadata = std::fs::metadata(target).ok();
+                let restored = write_synced(temp, contents, metadata)
+                    .and_then(|()| std::fs::rename(temp, target));
+                if restored.is_err() {
+                    let _ = std::fs::remove_file(temp);
+                }
+            }
+            None => {
+                let _ = std::fs::remove_file(target);
+            }
+        }
+    }
+}
+
+fn temp_path(target: &std::path::Path) -> crate::Result<std::path::PathBuf> {
+    let file_name = target.file_name().ok_or_else(|| {
+        Error::io(target)(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "not a file path",
+        ))
+    })?;
+    Ok(target.with_file_name(format!(
+        ".{}.{}.tmp",
+        file_name.to_string_lossy(),
+        std::process::id()
+    )))
+}
+
+/// Writes `contents` to `path`, with the permissions of `like` (the file it replaces) if given.
+fn write_synced(
+    path: &std::path::Path,
+    contents: &[u8],
+    like: Option<std::fs::Metadata>,
+) -> std::io::Result<()> {
+    let mut file = std::fs::File::create(path)?;
+    if let Some(like) = like {
+        file.set_permissions(like.permissions())?;
+    }
+    std::io::Write::write_all(&mut file, contents)?;
+    file.sync_all()
+}
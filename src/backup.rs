@@ -0,0 +1,253 @@
+use crate::{
+    Error,
+    drift::{self, InstallState},
+};
+
+/// Directory next to karabiner.json that holds the backups taken before each install.
+pub const BACKUP_DIR_NAME: &str = "karaconf_backups";
+
+const BACKUP_PREFIX: &str = "karabiner-";
+const BACKUP_SUFFIX: &str = ".json";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Backup {
+    /// UTC time the backup was taken, formatted as `YYYYMMDDTHHMMSSZ`, followed by `-<n>` for the
+    /// n-th further backup taken within the same second.
+    pub timestamp: String,
+    pub path: std::path::PathBuf,
+}
+
+impl Backup {
+    /// Orders backups chronologically, including those taken within the same second.
+    fn sort_key(&self) -> (&str, u32) {
+        match self.timestamp.split_once('-') {
+            Some((time, n)) => (time, n.parse().unwrap_or(u32::MAX)),
+            None => (&self.timestamp, 0),
+        }
+    }
+}
+
+fn backup_dir(karabiner_json_path: &std::path::Path) -> std::path::PathBuf {
+    karabiner_json_path
+        .parent()
+        .unwrap_or(std::path::Path::new("."))
+        .join(BACKUP_DIR_NAME)
+}
+
+/// Copies karabiner.json into the backup directory and deletes all but the newest `keep` backups.
+/// Returns `None` when there is no karabiner.json to back up.
+pub fn backup(karabiner_json_path: &std::path::Path, keep: usize) -> crate::Result<Option<Backup>> {
+    if !karabiner_json_path.exists() {
+        return Ok(None);
+    }
+    let dir = backup_dir(karabiner_json_path);
+    std::fs::create_dir_all(&dir).map_err(Error::io(&dir))?;
+    let time = timestamp(std::time::SystemTime::now());
+    // Backups taken earlier within the same second hold older states, so they are kept.
+    let (timestamp, path) = (0..)
+        .map(|n| match n {
+            0 => time.clone(),
+            n => format!("{}-{}", time, n),
+        })
+        .map(|timestamp| {
+            let path = dir.join(format!("{}{}{}", BACKUP_PREFIX, timestamp, BACKUP_SUFFIX));
+            (timestamp, path)
+        })
+        .find(|(_, path)| !path.exists())
+        .expect("a free backup file name");
+    std::fs::copy(karabiner_json_path, &path).map_err(Error::io(&path))?;
+
+    let backups = list_backups(karabiner_json_path)?;
+    for old in &backups[..backups.len().saturating_sub(keep.max(1))] {
+        std::fs::remove_file(&old.path).map_err(Error::io(&old.path))?;
+    }
+    Ok(Some(Backup { timestamp, path }))
+}
+
+/// Backups of karabiner.json, oldest first.
+pub fn list_backups(karabiner_json_path: &std::path::Path) -> crate::Result<Vec<Backup>> {
+    let dir = backup_dir(karabiner_json_path);
+    if !dir.is_dir() {
+        return Ok(vec![]);
+    }
+    let mut backups = std::fs::read_dir(&dir)
+        .map_err(Error::io(&dir))?
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let timestamp = path
+                .file_name()?
+                .to_str()?
+                .strip_prefix(BACKUP_PREFIX)?
+                .strip_suffix(BACKUP_SUFFIX)?
+                .to_string();
+            Some(Backup { timestamp, path })
+        })
+        .collect::<Vec<_>>();
+    backups.sort_by(|a, b| a.sort_key().cmp(&b.sort_key()));
+    Ok(backups)
+}
+
+/// Puts `backup` back in place of karabiner.json. The current karabiner.json is backed up first,
+/// so a restore can itself be undone. The install state is recomputed from the restored rules,
+/// so the next install does not take them for edits made outside karaconf.
+pub fn restore(
+    karabiner_json_path: &std::path::Path,
+    backup: &Backup,
+    keep: usize,
+) -> crate::Result<()> {
+    let data = std::fs::read(&backup.path).map_err(Error::io(&backup.path))?;
+    let karabiner_json = serde_json::from_slice::<serde_json::Value>(&data)
+        .map_err(Error::invalid_file(&backup.path))?;
+    let state = InstallState::from_karabiner_json(&karabiner_json);
+    let state_data = serde_json::to_vec_pretty(&state).map_err(Error::Json)?;
+    self::backup(karabiner_json_path, keep)?;
+    crate::atomic_write::write_all(&[
+        (karabiner_json_path, &data),
+        (&drift::state_path(karabiner_json_path), &state_data),
+    ])?;
+    Ok(())
+}
+
+fn timestamp(time: std::time::SystemTime) -> String {
+    let secs = time
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let (days, secs_of_day) = (secs / 86400, secs % 86400);
+
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A karabiner.json holding `contents` in an otherwise empty directory.
+    fn karabiner_json(name: &str, contents: &str) -> std::path::PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("karaconf-backup-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("karabiner.json");
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn contents(backups: &[Backup]) -> Vec<String> {
+        backups
+            .iter()
+            .map(|backup| std::fs::read_to_string(&backup.path).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn timestamps_are_utc_civil_dates() {
+        let at = |secs| timestamp(std::time::UNIX_EPOCH + std::time::Duration::from_secs(secs));
+        assert_eq!(at(0), "19700101T000000Z");
+        assert_eq!(at(951_782_400), "20000229T000000Z");
+        assert_eq!(at(1_709_251_199), "20240229T235959Z");
+        assert_eq!(at(4_102_444_799), "20991231T235959Z");
+    }
+
+    #[test]
+    fn backups_taken_within_a_second_are_all_kept_in_order() {
+        let path = karabiner_json("same-second", "1");
+        for contents in ["2", "3"] {
+            backup(&path, 10).unwrap();
+            std::fs::write(&path, contents).unwrap();
+        }
+        backup(&path, 10).unwrap();
+        assert_eq!(contents(&list_backups(&path).unwrap()), ["1", "2", "3"]);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn backups_sort_by_time_then_by_count() {
+        let path = karabiner_json("sort", "{}");
+        let dir = backup_dir(&path);
+        std::fs::create_dir_all(&dir).unwrap();
+        let timestamps = [
+            "20240229T235959Z",
+            "20240229T235959Z-2",
+            "20240229T235959Z-10",
+            "20240301T000000Z",
+        ];
+        for timestamp in timestamps.iter().rev() {
+            std::fs::write(dir.join(format!("karabiner-{}.json", timestamp)), "{}").unwrap();
+        }
+        let backups = list_backups(&path).unwrap();
+        let listed = backups
+            .iter()
+            .map(|b| b.timestamp.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(listed, timestamps);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn only_the_newest_backups_are_kept() {
+        let path = karabiner_json("keep", "1");
+        for contents in ["2", "3"] {
+            backup(&path, 2).unwrap();
+            std::fs::write(&path, contents).unwrap();
+        }
+        backup(&path, 2).unwrap();
+        assert_eq!(contents(&list_backups(&path).unwrap()), ["2", "3"]);
+
+        // The backup just taken is never deleted.
+        backup(&path, 0).unwrap();
+        assert_eq!(contents(&list_backups(&path).unwrap()), ["3"]);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn restore_backs_up_the_file_it_replaces() {
+        let path = karabiner_json("restore", r#"{"profiles": []}"#);
+        let before = backup(&path, 10).unwrap().unwrap();
+        std::fs::write(&path, r#"{"profiles": [{"name": "Work"}]}"#).unwrap();
+
+        restore(&path, &before, 10).unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            r#"{"profiles": []}"#
+        );
+        assert_eq!(
+            contents(&list_backups(&path).unwrap()),
+            [r#"{"profiles": []}"#, r#"{"profiles": [{"name": "Work"}]}"#]
+        );
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn restore_refuses_a_backup_that_does_not_parse() {
+        let path = karabiner_json("restore-invalid", "{}");
+        let broken = backup(&path, 10).unwrap().unwrap();
+        std::fs::write(&broken.path, "{").unwrap();
+
+        assert!(matches!(
+            restore(&path, &broken, 10),
+            Err(Error::InvalidFile { .. })
+        ));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "{}");
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,58 @@
+use crate::{
+    karabiner_data::{ComplexModifications, Rule},
+    profiles::{ProfileRules, ProfileTarget},
+    rule_sets::{self, RuleSet},
+};
+
+/// Everything karaconf generates: the rules each Karabiner profile receives.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Title of custom.json, shown in the "Add rule" dialog of Karabiner-Elements.
+    pub title: String,
+    pub profiles: Vec<ProfileRules>,
+}
+
+impl Config {
+    /// Rules for `target`. Profiles without an entry of their own get the rules of the first
+    /// entry, so `--profile <name>` works for any profile name.
+    pub fn rules_for(&self, target: &ProfileTarget) -> Vec<Rule> {
+        self.profiles
+            .iter()
+            .find(|profile| profile.target == *target)
+            .or_else(|| self.profiles.first())
+            .map(|profile| profile.rules.clone())
+            .unwrap_or_default()
+    }
+}
+
+/// The configuration `karaconf` installs: the selected rule sets, in the profiles of
+/// `profile_rules`.
+pub fn config(rule_sets: &[Box<dyn RuleSet>]) -> Config {
+    Config {
+        title: "Personal rules".to_string(),
+        profiles: profile_rules(rule_sets),
+    }
+}
+
+/// Rules installed into each Karabiner profile. Add an entry with
+/// `ProfileTarget::Named("Gaming".to_string())` to give a profile its own rule sets.
+pub fn profile_rules(rule_sets: &[Box<dyn RuleSet>]) -> Vec<ProfileRules> {
+    vec![ProfileRules {
+        target: ProfileTarget::First,
+        rules: rule_sets::rules(rule_sets),
+    }]
+}
+
+/// custom.json: every rule of every configured profile, once.
+pub fn generate(config: &Config) -> ComplexModifications {
+    let mut rules: Vec<Rule> = vec![];
+    for rule in config.profiles.iter().flat_map(|profile| &profile.rules) {
+        if !rules.iter().any(|r| r.description == rule.description) {
+            rules.push(rule.clone());
+        }
+    }
+    ComplexModifications {
+        title: config.title.clone(),
+        rules,
+    }
+}
@@ -0,0 +1,199 @@
+/// How one rule differs between the file on disk and the newly generated one.
+#[derive(Debug, PartialEq)]
+pub enum RuleDiff {
+    Added {
+        description: String,
+        manipulators: usize,
+    },
+    Removed {
+        description: String,
+        manipulators: usize,
+    },
+    Changed {
+        description: String,
+        added: Vec<serde_json::Value>,
+        removed: Vec<serde_json::Value>,
+    },
+    /// Same manipulators, evaluated in a different order.
+    Reordered { description: String },
+}
+
+impl std::fmt::Display for RuleDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RuleDiff::Added {
+                description,
+                manipulators,
+            } => write!(f, "+ {} ({} manipulators)", description, manipulators),
+            RuleDiff::Removed {
+                description,
+                manipulators,
+            } => write!(f, "- {} ({} manipulators)", description, manipulators),
+            RuleDiff::Changed {
+                description,
+                added,
+                removed,
+            } => {
+                write!(
+                    f,
+                    "~ {} (+{} -{} manipulators)",
+                    description,
+                    added.len(),
+                    removed.len()
+                )?;
+                for manipulator in removed {
+                    write!(f, "\n    - {}", manipulator)?;
+                }
+                for manipulator in added {
+                    write!(f, "\n    + {}", manipulator)?;
+                }
+                Ok(())
+            }
+            RuleDiff::Reordered { description } => {
+                write!(f, "~ {} (manipulators reordered)", description)
+            }
+        }
+    }
+}
+
+/// Compares two `rules` arrays, matching rules by description and manipulators by value.
+pub fn diff_rules(old: &[serde_json::Value], new: &[serde_json::Value]) -> Vec<RuleDiff> {
+    let description =
+        |rule: &serde_json::Value| rule["description"].as_str().unwrap_or_default().to_string();
+    let manipulators =
+        |rule: &serde_json::Value| rule["manipulators"].as_array().cloned().unwrap_or_default();
+
+    let mut diffs = vec![];
+    for old_rule in old {
+        if !new.iter().any(|r| description(r) == description(old_rule)) {
+            diffs.push(RuleDiff::Removed {
+                description: description(old_rule),
+                manipulators: manipulators(old_rule).len(),
+            });
+        }
+    }
+    for new_rule in new {
+        let Some(old_rule) = old.iter().find(|r| description(r) == description(new_rule)) else {
+            diffs.push(RuleDiff::Added {
+                description: description(new_rule),
+                manipulators: manipulators(new_rule).len(),
+            });
+            continue;
+        };
+        let (old_manipulators, new_manipulators) = (manipulators(old_rule), manipulators(new_rule));
+        if old_manipulators == new_manipulators {
+            continue;
+        }
+        let removed = remaining(&old_manipulators, &new_manipulators);
+        let added = remaining(&new_manipulators, &old_manipulators);
+        diffs.push(if added.is_empty() && removed.is_empty() {
+            RuleDiff::Reordered {
+                description: description(new_rule),
+            }
+        } else {
+            RuleDiff::Changed {
+                description: description(new_rule),
+                added,
+                removed,
+            }
+        });
+    }
+    diffs
+}
+
+/// Rule fields other than `manipulators` that differ between `old` and `new`, with the old and
+/// the new value (`Null` where the field is missing). `diff_rules` does not report these.
+pub fn rule_field_changes(
+    old: &serde_json::Value,
+    new: &serde_json::Value,
+) -> Vec<(String, serde_json::Value, serde_json::Value)> {
+    let empty = serde_json::Map::new();
+    let (old, new) = (
+        old.as_object().unwrap_or(&empty),
+        new.as_object().unwrap_or(&empty),
+    );
+    let mut changes = vec![];
+    for field in old
+        .keys()
+        .chain(new.keys().filter(|field| !old.contains_key(*field)))
+    {
+        if field == "manipulators" {
+            continue;
+        }
+        let (old_value, new_value) = (
+            old.get(field).cloned().unwrap_or_default(),
+            new.get(field).cloned().unwrap_or_default(),
+        );
+        if old_value != new_value {
+            changes.push((field.clone(), old_value, new_value));
+        }
+    }
+    changes
+}
+
+/// Items of `values` left over after removing one occurrence of each item of `other`.
+fn remaining(values: &[serde_json::Value], other: &[serde_json::Value]) -> Vec<serde_json::Value> {
+    let mut other = other.to_vec();
+    values
+        .iter()
+        .filter(|value| match other.iter().position(|o| o == *value) {
+            Some(i) => {
+                other.remove(i);
+                false
+            }
+            None => true,
+        })
+        .cloned()
+        .collect()
+}
+
+/// Rule-level differences of every profile in two karabiner.json documents, keyed by profile name.
+pub fn diff_karabiner_json(
+    old: &serde_json::Value,
+    new: &serde_json::Value,
+) -> Vec<(String, Vec<RuleDiff>)> {
+    let profiles = |json: &serde_json::Value| {
+        json["profiles"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|profile| {
+                (
+                    profile["name"].as_str().unwrap_or_default().to_string(),
+                    profile["complex_modifications"]["rules"]
+                        .as_array()
+                        .cloned()
+                        .unwrap_or_default(),
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+    let old_profiles = profiles(old);
+    profiles(new)
+        .into_iter()
+        .filter_map(|(name, new_rules)| {
+            let old_rules = old_profiles
+                .iter()
+                .find(|(old_name, _)| *old_name == name)
+                .map(|(_, rules)| rules.as_slice())
+                .unwrap_or_default();
+            let diffs = diff_rules(old_rules, &new_rules);
+            (!diffs.is_empty()).then_some((name, diffs))
+        })
+        .collect()
+}
+
+/// A unified diff of two texts, or an empty string when they are equal.
+pub fn text_diff(old: &str, new: &str, path: &std::path::Path) -> String {
+    if old == new {
+        return String::new();
+    }
+    similar::TextDiff::from_lines(old, new)
+        .unified_diff()
+        .header(
+            &format!("{} (on disk)", path.display()),
+            &format!("{} (generated)", path.display()),
+        )
+        .to_string()
+}
@@ -0,0 +1,139 @@
+use crate::{
+    Error,
+    merge::{OWNED_RULE_PREFIX, RULE_STATE_FIELDS},
+};
+
+/// File next to karabiner.json that records what the last install wrote.
+pub const STATE_FILE_NAME: &str = "karaconf_state.json";
+
+/// Hashes of the karaconf-owned rules each profile received on the last install, keyed by
+/// profile name and then by rule description.
+#[derive(Debug, Default, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct InstallState {
+    pub profiles: std::collections::BTreeMap<String, std::collections::BTreeMap<String, String>>,
+}
+
+pub fn state_path(karabiner_json_path: &std::path::Path) -> std::path::PathBuf {
+    karabiner_json_path
+        .parent()
+        .unwrap_or(std::path::Path::new("."))
+        .join(STATE_FILE_NAME)
+}
+
+impl InstallState {
+    /// Reads the state recorded next to karabiner.json; nothing recorded yet is an empty state.
+    pub fn load(karabiner_json_path: &std::path::Path) -> crate::Result<Self> {
+        let path = state_path(karabiner_json_path);
+        if !path.exists() {
+            return Ok(InstallState::default());
+        }
+        serde_json::from_slice(&std::fs::read(&path).map_err(Error::io(&path))?)
+            .map_err(Error::invalid_file(&path))
+    }
+
+    /// Owned rules in `rules` that differ from what karaconf installed into `profile` last time,
+    /// i.e. rules edited through the Karabiner UI since. Rules karaconf has no record of are not
+    /// reported, so the first install after upgrading never refuses.
+    pub fn drifted(&self, profile: &str, rules: &[serde_json::Value]) -> Vec<serde_json::Value> {
+        let Some(recorded) = self.profiles.get(profile) else {
+            return vec![];
+        };
+        rules
+            .iter()
+            .filter(|rule| {
+                let Some(description) = rule["description"].as_str() else {
+                    return false;
+                };
+                description.starts_with(OWNED_RULE_PREFIX)
+                    && recorded
+                        .get(description)
+                        .is_some_and(|hash| *hash != rule_hash(rule))
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Owned rules karaconf installed into `profile` last time that are no longer in `rules`,
+    /// i.e. rules deleted through the Karabiner UI since.
+    pub fn deleted(&self, profile: &str, rules: &[serde_json::Value]) -> Vec<String> {
+        let Some(recorded) = self.profiles.get(profile) else {
+            return vec![];
+        };
+        recorded
+            .keys()
+            .filter(|description| {
+                !rules
+                    .iter()
+                    .any(|rule| rule["description"].as_str() == Some(description.as_str()))
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// The state an install that wrote `karabiner_json` would have recorded, so that after a
+    /// backup is restored its owned rules count as installed rather than edited or deleted.
+    pub fn from_karabiner_json(karabiner_json: &serde_json::Value) -> Self {
+        let mut state = InstallState::default();
+        for profile in karabiner_json["profiles"].as_array().into_iter().flatten() {
+            if let (Some(name), Some(rules)) = (
+                profile["name"].as_str(),
+                profile["complex_modifications"]["rules"].as_array(),
+            ) {
+                state.record(name, rules);
+            }
+        }
+        state
+    }
+
+    /// Replaces the record of `profile` with the owned rules among `rules`.
+    pub fn record(&mut self, profile: &str, rules: &[serde_json::Value]) {
+        let hashes = rules
+            .iter()
+            .filter_map(|rule| {
+                let description = rule["description"].as_str()?;
+                description
+                    .starts_with(OWNED_RULE_PREFIX)
+                    .then(|| (description.to_string(), rule_hash(rule)))
+            })
+            .collect();
+        self.profiles.insert(profile.to_string(), hashes);
+    }
+}
+
+/// A hash of the rule's content that does not depend on key order or formatting, since
+/// Karabiner rewrites karabiner.json in its own layout. `RULE_STATE_FIELDS` are left out, as
+/// installs keep them, so turning a rule off in the UI is not an edit.
+pub fn rule_hash(rule: &serde_json::Value) -> String {
+    let mut content = canonical(rule);
+    if let Some(content) = content.as_object_mut() {
+        for field in RULE_STATE_FIELDS {
+            content.remove(*field);
+        }
+    }
+    // FNV-1a, which unlike `DefaultHasher` is stable across Rust releases.
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in content.to_string().bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", hash)
+}
+
+fn canonical(value: &serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(map) => {
+            let mut entries = map.iter().collect::<Vec<_>>();
+            entries.sort_by_key(|(key, _)| *key);
+            serde_json::Value::Object(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key.clone(), canonical(value)))
+                    .collect(),
+            )
+        }
+        serde_json::Value::Array(values) => {
+            serde_json::Value::Array(values.iter().map(canonical).collect())
+        }
+        value => value.clone(),
+    }
+}
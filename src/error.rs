@@ -0,0 +1,148 @@
+use crate::{analysis::Conflict, profiles::ProfileTarget, validate::Invalid};
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug)]
+pub enum Error {
+    /// Neither a config directory was given nor `HOME` is set.
+    NoHome,
+    /// The Karabiner-Elements configuration directory does not exist yet.
+    ConfigDirMissing(std::path::PathBuf),
+    /// There is no karabiner.json in the configuration directory yet.
+    KarabinerJsonMissing(std::path::PathBuf),
+    InvalidKarabinerJson {
+        path: std::path::PathBuf,
+        source: serde_json::Error,
+    },
+    /// karabiner.json is valid JSON but not shaped the way Karabiner-Elements writes it, e.g.
+    /// `what` is `"profiles"` and `expected` is `"an array"`.
+    UnexpectedKarabinerJson {
+        path: std::path::PathBuf,
+        what: String,
+        expected: String,
+    },
+    /// karabiner.json has no profile to install into.
+    NoProfile(std::path::PathBuf),
+    /// No profile with this name exists in the karabiner.json at `path`.
+    ProfileNotFound {
+        path: std::path::PathBuf,
+        name: String,
+    },
+    Io {
+        path: std::path::PathBuf,
+        source: std::io::Error,
+    },
+    /// A file karaconf reads besides karabiner.json (e.g. rule_sets.json or a backup) is not
+    /// valid JSON or not in the expected format.
+    InvalidFile {
+        path: std::path::PathBuf,
+        source: serde_json::Error,
+    },
+    UnknownRuleSet {
+        name: String,
+        available: Vec<String>,
+    },
+    /// Generated manipulators that Karabiner would reject or ignore; nothing is written.
+    Invalid(Vec<Invalid>),
+    /// Conflicting bindings, reported only when asked for (see `Installer::deny_conflicts`).
+    Conflicts(Vec<Conflict>),
+    /// Owned rules were edited outside karaconf since the last install, and `force` is not set.
+    Drifted { rules: Vec<(ProfileTarget, String)> },
+    /// Generated data could not be serialized.
+    Json(serde_json::Error),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::NoHome => write!(f, "HOME environment variable is not set"),
+            Error::ConfigDirMissing(path) => {
+                write!(f, "{:?} must be created via Karabiner-Elements", path)
+            }
+            Error::KarabinerJsonMissing(path) => write!(
+                f,
+                "{:?} does not exist; start Karabiner-Elements once to create it",
+                path
+            ),
+            Error::InvalidKarabinerJson { path, .. } => write!(f, "Failed to parse {:?}", path),
+            Error::UnexpectedKarabinerJson {
+                path,
+                what,
+                expected,
+            } => write!(
+                f,
+                "Invalid format of {:?}: {} is not {}",
+                path, what, expected
+            ),
+            Error::NoProfile(path) => write!(f, "No profile exists in {:?}", path),
+            Error::ProfileNotFound { path, name } => {
+                write!(f, "No profile named {:?} exists in {:?}", name, path)
+            }
+            Error::Io { path, .. } => write!(f, "Failed to access {:?}", path),
+            Error::InvalidFile { path, .. } => write!(f, "Failed to parse {:?}", path),
+            Error::UnknownRuleSet { name, available } => write!(
+                f,
+                "Unknown rule set {:?}; available: {}",
+                name,
+                available.join(", ")
+            ),
+            Error::Invalid(invalid) => {
+                write!(f, "{} problem(s) in the generated rules:", invalid.len())?;
+                for invalid in invalid {
+                    write!(f, "\n    {}", invalid)?;
+                }
+                Ok(())
+            }
+            Error::Conflicts(conflicts) => {
+                write!(f, "{} conflicting binding(s):", conflicts.len())?;
+                for conflict in conflicts {
+                    write!(f, "\n    {}", conflict)?;
+                }
+                Ok(())
+            }
+            Error::Drifted { rules } => {
+                write!(
+                    f,
+                    "{} rule(s) were edited outside karaconf since the last install:",
+                    rules.len()
+                )?;
+                for (target, description) in rules {
+                    write!(f, "\n    {} in {}", description, target)?;
+                }
+                Ok(())
+            }
+            Error::Json(_) => write!(f, "Failed to serialize the generated JSON"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            Error::InvalidKarabinerJson { source, .. } | Error::InvalidFile { source, .. } => {
+                Some(source)
+            }
+            Error::Json(source) => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl Error {
+    pub(crate) fn io(path: &std::path::Path) -> impl FnOnce(std::io::Error) -> Error + '_ {
+        move |source| Error::Io {
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+
+    pub(crate) fn invalid_file(
+        path: &std::path::Path,
+    ) -> impl FnOnce(serde_json::Error) -> Error + '_ {
+        move |source| Error::InvalidFile {
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+}
@@ -0,0 +1,631 @@
+use crate::{
+    Error,
+    karabiner_data::{
+        BundleIdentifier, Condition, ConditionType, FromEvent, FromModifier, Manipulator,
+        ManipulatorType, ModifierKey, MouseKey, Rule, SetVariable, To, VirtualKey,
+    },
+    merge::OWNED_RULE_PREFIX,
+};
+use std::fmt::Write as _;
+
+/// A `rule_sets::<module_name>` module generated from one rule of an existing karabiner.json.
+#[derive(Debug)]
+pub struct ImportedRuleSet {
+    pub module_name: String,
+    pub description: String,
+    pub source: String,
+    /// Rule-level fields (e.g. `available_since`) that the generated module does not carry over.
+    pub dropped_fields: Vec<String>,
+}
+
+/// Collects the rules of every profile (or only of `profile`) in karabiner.json, read from `path`.
+/// Rules that appear in several profiles are imported once.
+pub fn rules_from_karabiner_json(
+    karabiner_json: &serde_json::Value,
+    path: &std::path::Path,
+    profile: Option<&str>,
+) -> crate::Result<Vec<Rule>> {
+    let profiles =
+        karabiner_json["profiles"]
+            .as_array()
+            .ok_or_else(|| Error::UnexpectedKarabinerJson {
+                path: path.to_path_buf(),
+                what: "profiles".to_string(),
+                expected: "an array".to_string(),
+            })?;
+    let mut rules: Vec<Rule> = vec![];
+    for p in profiles {
+        if profile.is_some_and(|name| p["name"].as_str() != Some(name)) {
+            continue;
+        }
+        let Some(profile_rules) = p["complex_modifications"].get("rules") else {
+            continue;
+        };
+        let profile_rules =
+            serde_json::from_value::<Vec<Rule>>(profile_rules.clone()).map_err(|e| {
+                Error::UnexpectedKarabinerJson {
+                    path: path.to_path_buf(),
+                    what: format!("complex_modifications.rules of {:?}", p["name"]),
+                    expected: format!("a list of rules ({})", e),
+                }
+            })?;
+        for rule in profile_rules {
+            if !rules.contains(&rule) {
+                rules.push(rule);
+            }
+        }
+    }
+    if let Some(name) = profile {
+        if !profiles.iter().any(|p| p["name"].as_str() == Some(name)) {
+            return Err(Error::ProfileNotFound {
+                path: path.to_path_buf(),
+                name: name.to_string(),
+            });
+        }
+    }
+    Ok(rules)
+}
+
+/// Generates one rule-set module per rule. `taken` holds module names that must not be reused.
+pub fn rule_sets_from_rules(rules: &[Rule], taken: &[String]) -> Vec<ImportedRuleSet> {
+    let mut module_names: Vec<String> = taken.to_vec();
+    rules
+        .iter()
+        .map(|rule| {
+            let base = module_name(rule_set_description(rule));
+            let mut name = base.clone();
+            let mut n = 2;
+            while module_names.contains(&name) {
+                name = format!("{}_{}", base, n);
+                n += 1;
+            }
+            module_names.push(name.clone());
+            ImportedRuleSet {
+                module_name: name,
+                description: rule_set_description(rule).to_string(),
+                source: module_source(rule),
+                dropped_fields: rule.extra.keys().cloned().collect(),
+            }
+        })
+        .collect()
+}
+
+/// The rule set's `DESCRIPTION`: the rule's description without the prefix karaconf adds on install.
+fn rule_set_description(rule: &Rule) -> &str {
+    rule.description
+        .strip_prefix(OWNED_RULE_PREFIX)
+        .unwrap_or(&rule.description)
+}
+
+fn module_name(description: &str) -> String {
+    let mut name = String::new();
+    for c in description.chars() {
+        if c.is_ascii_alphanumeric() {
+            name.push(c.to_ascii_lowercase());
+        } else if !name.is_empty() && !name.ends_with('_') {
+            name.push('_');
+        }
+    }
+    let name = name.trim_end_matches('_').to_string();
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) || is_keyword(&name) {
+        format!("rule_{}", name).trim_end_matches('_').to_string()
+    } else {
+        name
+    }
+}
+
+fn is_keyword(name: &str) -> bool {
+    [
+        "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+        "extern", "false", "fn", "for", "gen", "if", "impl", "in", "let", "loop", "match", "mod",
+        "move", "mut", "pub", "ref", "return", "self", "static", "struct", "super", "trait",
+        "true", "type", "unsafe", "use", "where", "while",
+    ]
+    .contains(&name)
+}
+
+/// Rust source of a `rule_sets::<name>` module whose `manipulators()` reproduces `rule`, laid out
+/// the way rustfmt formats it.
+pub fn module_source(rule: &Rule) -> String {
+    let mut body = String::new();
+    let mut uses_modifier_keys = false;
+    for manipulator in &rule.manipulators {
+        match builder_chain(manipulator) {
+            Some(calls) => {
+                uses_modifier_keys |= has_modifier_keys(manipulator);
+                let _ = writeln!(
+                    body,
+                    "        {},",
+                    chain("Manipulator::builder()", &calls, 8, 1)
+                );
+            }
+            None => {
+                let _ = writeln!(
+                    body,
+                    "        // Not expressible with Manipulator::builder()"
+                );
+                let from_value = Expr::call(
+                    "serde_json::from_value",
+                    vec![Expr::Json(serde_json::to_value(manipulator).unwrap())],
+                )
+                .render(8, 8, 0);
+                // rustfmt puts the call after a multi-line parent on a line of its own.
+                let separator = if from_value.contains('\n') {
+                    "\n        "
+                } else {
+                    ""
+                };
+                let _ = writeln!(body, "        {}{}.unwrap(),", from_value, separator);
+            }
+        }
+    }
+
+    // `VK::` ends with `K::`, so only a `K::` that does not continue an identifier counts.
+    let uses_path = |path: &str| {
+        body.match_indices(path)
+            .any(|(i, _)| !body[..i].ends_with(|c: char| c.is_ascii_alphanumeric() || c == '_'))
+    };
+    let mut imports = vec![];
+    if uses_path("K::") {
+        imports.push("KeyCode as K");
+    }
+    if uses_modifier_keys {
+        imports.push("ModifierKey::*");
+    }
+    if uses_path("VK::") {
+        imports.push("VirtualKey as VK");
+    }
+    imports.push("*");
+
+    let mut source = String::new();
+    if imports.len() == 1 {
+        let _ = writeln!(source, "use crate::karabiner_data::*;");
+    } else {
+        let _ = writeln!(
+            source,
+            "use crate::karabiner_data::{{{}}};",
+            imports.join(", ")
+        );
+    }
+    let _ = writeln!(source);
+    let _ = writeln!(
+        source,
+        "pub const DESCRIPTION: &str = {:?};",
+        rule_set_description(rule)
+    );
+    let _ = writeln!(source);
+    let _ = writeln!(source, "pub fn manipulators() -> Vec<Manipulator> {{");
+    let _ = writeln!(source, "    vec![");
+    source.push_str(&body);
+    let _ = writeln!(source, "    ]");
+    let _ = writeln!(source, "}}");
+    source
+}
+
+fn has_modifier_keys(manipulator: &Manipulator) -> bool {
+    manipulator
+        .from
+        .modifiers
+        .as_ref()
+        .is_some_and(|modifiers| {
+            !modifiers.mandatory().is_empty() || !modifiers.optional().is_empty()
+        })
+        || manipulator.to.iter().any(
+            |to| matches!(to, To::Key { modifiers: Some(modifiers), .. } if !modifiers.is_empty()),
+        )
+}
+
+/// rustfmt's default `max_width`.
+const MAX_WIDTH: usize = 100;
+/// rustfmt's default `fn_call_width`, `array_width` and `chain_width`.
+const CALL_WIDTH: usize = 60;
+/// rustfmt's default `struct_lit_width`.
+const STRUCT_LIT_WIDTH: usize = 18;
+
+/// An expression of a generated module, rendered the way rustfmt lays it out.
+enum Expr {
+    Atom(String),
+    /// `callee(args)`, or `callee![args]` when `callee` is a macro such as `vec!`.
+    Call(String, Vec<Expr>),
+    Struct(String, Vec<(&'static str, Expr)>),
+    Json(serde_json::Value),
+}
+
+impl Expr {
+    fn call(callee: &str, args: Vec<Expr>) -> Expr {
+        Expr::Call(callee.to_string(), args)
+    }
+
+    fn atom(atom: impl Into<String>) -> Expr {
+        Expr::Atom(atom.into())
+    }
+
+    /// The expression on one line, or `None` when rustfmt breaks it whatever room is left.
+    fn flat(&self) -> Option<String> {
+        match self {
+            Expr::Atom(atom) => Some(atom.clone()),
+            Expr::Call(callee, args) => {
+                let args = args
+                    .iter()
+                    .map(Expr::flat)
+                    .collect::<Option<Vec<_>>>()?
+                    .join(", ");
+                let (open, close) = delimiters(callee);
+                (args.len() <= CALL_WIDTH).then(|| format!("{}{}{}{}", callee, open, args, close))
+            }
+            Expr::Struct(path, fields) => {
+                let fields = fields
+                    .iter()
+                    .map(|(name, value)| Some(format!("{}: {}", name, value.flat()?)))
+                    .collect::<Option<Vec<_>>>()?
+                    .join(", ");
+                (fields.len() <= STRUCT_LIT_WIDTH).then(|| format!("{} {{ {} }}", path, fields))
+            }
+            Expr::Json(value) => Some(json_macro(value, 0)).filter(|json| !json.contains('\n')),
+        }
+    }
+
+    /// Renders the expression starting at `column` of a line indented by `indent`, followed by
+    /// `suffix` more characters on its last line.
+    fn render(&self, indent: usize, column: usize, suffix: usize) -> String {
+        match self.flat() {
+            Some(flat) if column + flat.len() + suffix <= MAX_WIDTH => flat,
+            _ => self.render_broken(indent),
+        }
+    }
+
+    /// `render` for an expression that does not fit on one line.
+    fn render_broken(&self, indent: usize) -> String {
+        let inner = " ".repeat(indent + 4);
+        match self {
+            Expr::Atom(atom) => atom.clone(),
+            Expr::Json(value) => json_macro(value, indent / 4),
+            Expr::Struct(path, fields) => {
+                let mut rendered = format!("{} {{\n", path);
+                for (name, value) in fields {
+                    let value = value.render(indent + 4, indent + 4 + name.len() + 2, 1);
+                    let _ = writeln!(rendered, "{}{}: {},", inner, name, value);
+                }
+                rendered + &" ".repeat(indent) + "}"
+            }
+            Expr::Call(callee, args) => {
+                let (open, close) = delimiters(callee);
+                // A single struct, call or macro argument continues on the same line, and since
+                // the call did not fit on one line, neither does the argument.
+                if let [arg @ (Expr::Call(..) | Expr::Struct(..) | Expr::Json(_))] = &args[..] {
+                    let arg = arg.render_broken(indent);
+                    return format!("{}{}{}{}", callee, open, arg, close);
+                }
+                let mut rendered = format!("{}{}\n", callee, open);
+                for arg in args {
+                    let arg = arg.render(indent + 4, indent + 4, 1);
+                    let _ = writeln!(rendered, "{}{},", inner, arg);
+                }
+                rendered + &" ".repeat(indent) + close
+            }
+        }
+    }
+}
+
+fn delimiters(callee: &str) -> (&'static str, &'static str) {
+    if callee.ends_with('!') {
+        ("[", "]")
+    } else {
+        ("(", ")")
+    }
+}
+
+/// `base` followed by method `calls`, on one line when it fits in `CALL_WIDTH`, otherwise with
+/// one call per line.
+fn chain(base: &str, calls: &[Expr], indent: usize, suffix: usize) -> String {
+    let flat = calls
+        .iter()
+        .map(Expr::flat)
+        .collect::<Option<Vec<_>>>()
+        .map(|calls| format!("{}{}", base, calls.concat()))
+        .filter(|flat| flat.len() <= CALL_WIDTH && indent + flat.len() + suffix <= MAX_WIDTH);
+    if let Some(flat) = flat {
+        return flat;
+    }
+    let inner = " ".repeat(indent + 4);
+    let mut rendered = base.to_string();
+    for (i, call) in calls.iter().enumerate() {
+        let suffix = if i + 1 == calls.len() { suffix } else { 0 };
+        let _ = write!(
+            rendered,
+            "\n{}{}",
+            inner,
+            call.render(indent + 4, indent + 4, suffix)
+        );
+    }
+    rendered
+}
+
+/// The calls of `Manipulator::builder()...build()`, or `None` when the builder cannot reproduce
+/// `manipulator`.
+fn builder_chain(manipulator: &Manipulator) -> Option<Vec<Expr>> {
+    if manipulator.r#type != ManipulatorType::Basic
+        || !manipulator.extra.is_empty()
+        || !manipulator.from.extra.is_empty()
+    {
+        return None;
+    }
+    let Some(FromEvent::KeyCode(from_key)) = &manipulator.from.event else {
+        return None;
+    };
+    let key = |key_code| Expr::atom(format!("K::{:?}", key_code));
+
+    let mut calls = vec![];
+    match &manipulator.conditions {
+        Some(conditions) if conditions.is_empty() => {
+            calls.push(Expr::call(".conditions", vec![Expr::atom("vec![]")]))
+        }
+        Some(conditions) => calls.extend(
+            conditions
+                .iter()
+                .map(|condition| Expr::call(".condition", vec![condition_expr(condition)])),
+        ),
+        None => {}
+    }
+    calls.push(match &manipulator.from.modifiers {
+        None => Expr::call(".from_key", vec![key(from_key)]),
+        Some(modifiers) => Expr::call(
+            ".from_key_with_modifiers",
+            vec![key(from_key), from_modifier_expr(modifiers)],
+        ),
+    });
+    for to in &manipulator.to {
+        calls.push(match to {
+            To::Variable { set_variable } => {
+                Expr::call(".to_variable", vec![set_variable_expr(set_variable)])
+            }
+            To::Key {
+                key_code,
+                modifiers,
+            } => Expr::call(
+                ".to_key",
+                vec![
+                    key(key_code),
+                    match modifiers {
+                        Some(modifiers) => Expr::call("Some", vec![modifier_keys_expr(modifiers)]),
+                        None => Expr::atom("None"),
+                    },
+                ],
+            ),
+            To::Mouse { mouse_key } => Expr::call(".to_mouse", vec![mouse_key_expr(mouse_key)]),
+            To::Click { pointing_button } => Expr::call(
+                ".to_click",
+                vec![Expr::atom(format!("PointingButton::{:?}", pointing_button))],
+            ),
+            To::Command { shell_command } => Expr::call(
+                ".to_command",
+                vec![Expr::atom(format!("{:?}", shell_command))],
+            ),
+            To::Other(_) => return None,
+        });
+    }
+    for to in manipulator.to_after_key_up.iter().flatten() {
+        match to {
+            To::Variable { set_variable } => calls.push(Expr::call(
+                ".to_after_key_up",
+                vec![set_variable_expr(set_variable)],
+            )),
+            _ => return None,
+        }
+    }
+    for to in manipulator.to_if_alone.iter().flatten() {
+        match to {
+            To::Key {
+                key_code,
+                modifiers: None,
+            } => calls.push(Expr::call(".to_if_alone", vec![key(key_code)])),
+            _ => return None,
+        }
+    }
+    // The builder leaves these as `None` when nothing is pushed, so `Some(vec![])` cannot be reproduced.
+    if manipulator
+        .to_after_key_up
+        .as_ref()
+        .is_some_and(Vec::is_empty)
+        || manipulator.to_if_alone.as_ref().is_some_and(Vec::is_empty)
+    {
+        return None;
+    }
+    calls.push(Expr::call(".build", vec![]));
+    Some(calls)
+}
+
+fn condition_expr(condition: &Condition) -> Expr {
+    match condition {
+        Condition::OnApplication {
+            r#type: ConditionType::FrontmostApplicationIf,
+            bundle_identifiers,
+        } if bundle_identifiers.len() == 1 => Expr::call(
+            "Condition::on_app",
+            vec![bundle_identifier_expr(&bundle_identifiers[0])],
+        ),
+        Condition::WithVirtualKey {
+            r#type: ConditionType::VariableIf,
+            name,
+            value: 1,
+        } => match name {
+            VirtualKey::Vk1 => Expr::atom("Condition::with_vk1()"),
+            VirtualKey::Vk2 => Expr::atom("Condition::with_vk2()"),
+            VirtualKey::Vk3 => Expr::atom("Condition::with_vk3()"),
+            VirtualKey::Vk4 => Expr::atom("Condition::with_vk4()"),
+            VirtualKey::Other(_) => {
+                Expr::call("Condition::with_virtual_key", vec![virtual_key_expr(name)])
+            }
+        },
+        Condition::OnApplication {
+            r#type,
+            bundle_identifiers,
+        } => Expr::Struct(
+            "Condition::OnApplication".to_string(),
+            vec![
+                ("r#type", Expr::atom(format!("ConditionType::{:?}", r#type))),
+                (
+                    "bundle_identifiers",
+                    Expr::call(
+                        "vec!",
+                        bundle_identifiers
+                            .iter()
+                            .map(bundle_identifier_expr)
+                            .collect(),
+                    ),
+                ),
+            ],
+        ),
+        Condition::WithVirtualKey {
+            r#type,
+            name,
+            value,
+        } => Expr::Struct(
+            "Condition::WithVirtualKey".to_string(),
+            vec![
+                ("r#type", Expr::atom(format!("ConditionType::{:?}", r#type))),
+                ("name", virtual_key_expr(name)),
+                ("value", Expr::atom(value.to_string())),
+            ],
+        ),
+        Condition::Other(value) => Expr::call("Condition::Other", vec![Expr::Json(value.clone())]),
+    }
+}
+
+fn bundle_identifier_expr(bundle_identifier: &BundleIdentifier) -> Expr {
+    match bundle_identifier {
+        BundleIdentifier::Other(bundle_identifier) => Expr::call(
+            "BundleIdentifier::Other",
+            vec![Expr::atom(format!("{:?}.to_string()", bundle_identifier))],
+        ),
+        known => Expr::atom(format!("BundleIdentifier::{:?}", known)),
+    }
+}
+
+fn virtual_key_expr(virtual_key: &VirtualKey) -> Expr {
+    match virtual_key {
+        VirtualKey::Other(name) => Expr::call(
+            "VK::Other",
+            vec![Expr::atom(format!("{:?}.to_string()", name))],
+        ),
+        known => Expr::atom(format!("VK::{:?}", known)),
+    }
+}
+
+fn set_variable_expr(set_variable: &SetVariable) -> Expr {
+    Expr::Struct(
+        "SetVariable".to_string(),
+        vec![
+            ("name", virtual_key_expr(&set_variable.name)),
+            ("value", Expr::atom(set_variable.value.to_string())),
+        ],
+    )
+}
+
+fn mouse_key_expr(mouse_key: &MouseKey) -> Expr {
+    Expr::Struct(
+        "MouseKey".to_string(),
+        vec![
+            ("x", Expr::atom(format!("{:?}", mouse_key.x))),
+            ("y", Expr::atom(format!("{:?}", mouse_key.y))),
+            (
+                "vertical_wheel",
+                Expr::atom(format!("{:?}", mouse_key.vertical_wheel)),
+            ),
+        ],
+    )
+}
+
+fn from_modifier_expr(modifier: &FromModifier) -> Expr {
+    match modifier {
+        FromModifier::Optional(optional) => {
+            Expr::call("FromModifier::Optional", vec![modifier_keys_expr(optional)])
+        }
+        FromModifier::Mandatory(mandatory) => Expr::call(
+            "FromModifier::Mandatory",
+            vec![modifier_keys_expr(mandatory)],
+        ),
+        FromModifier::MandatoryAndOptional {
+            mandatory,
+            optional,
+        } => Expr::Struct(
+            "FromModifier::MandatoryAndOptional".to_string(),
+            vec![
+                ("mandatory", modifier_keys_expr(mandatory)),
+                ("optional", modifier_keys_expr(optional)),
+            ],
+        ),
+    }
+}
+
+fn modifier_keys_expr(modifier_keys: &[ModifierKey]) -> Expr {
+    Expr::call(
+        "vec!",
+        modifier_keys
+            .iter()
+            .map(|modifier_key| Expr::atom(format!("{:?}", modifier_key)))
+            .collect(),
+    )
+}
+
+/// Renders `value` as a `serde_json::json!` invocation. Strings use Rust escapes so the literal is always valid.
+fn json_macro(value: &serde_json::Value, depth: usize) -> String {
+    format!("serde_json::json!({})", json_literal(value, depth))
+}
+
+fn json_literal(value: &serde_json::Value, depth: usize) -> String {
+    let indent = "    ".repeat(depth + 1);
+    let closing = "    ".repeat(depth);
+    match value {
+        serde_json::Value::String(s) => format!("{:?}", s),
+        serde_json::Value::Array(values) if !values.is_empty() => format!(
+            "[\n{}\n{}]",
+            values
+                .iter()
+                .map(|v| format!("{}{}", indent, json_literal(v, depth + 1)))
+                .collect::<Vec<_>>()
+                .join(",\n"),
+            closing
+        ),
+        serde_json::Value::Object(map) if !map.is_empty() => format!(
+            "{{\n{}\n{}}}",
+            map.iter()
+                .map(|(k, v)| format!("{}{:?}: {}", indent, k, json_literal(v, depth + 1)))
+                .collect::<Vec<_>>()
+                .join(",\n"),
+            closing
+        ),
+        other => other.to_string(),
+    }
+}
+
+/// Writes the generated modules into `rule_sets_dir` and registers them in its `mod.rs`.
+pub fn write_rule_sets(
+    rule_sets_dir: &std::path::Path,
+    rule_sets: &[ImportedRuleSet],
+) -> crate::Result<()> {
+    for rule_set in rule_sets {
+        let path = rule_sets_dir.join(format!("{}.rs", rule_set.module_name));
+        std::fs::write(&path, &rule_set.source).map_err(Error::io(&path))?;
+    }
+    let mod_rs_path = rule_sets_dir.join("mod.rs");
+    let mod_rs = std::fs::read_to_string(&mod_rs_path).unwrap_or_default();
+    // Only the leading block of `pub mod` lines is sorted; the registry below it is left as is.
+    let mut lines: Vec<String> = mod_rs.lines().map(str::to_string).collect();
+    let rest = lines.split_off(
+        lines
+            .iter()
+            .position(|line| !line.starts_with("pub mod "))
+            .unwrap_or(lines.len()),
+    );
+    for rule_set in rule_sets {
+        let line = format!("pub mod {};", rule_set.module_name);
+        if !lines.contains(&line) {
+            lines.push(line);
+        }
+    }
+    lines.sort();
+    lines.extend(rest);
+    std::fs::write(&mod_rs_path, lines.join("\n") + "\n").map_err(Error::io(&mod_rs_path))?;
+    Ok(())
+}
@@ -0,0 +1,291 @@
+use crate::{
+    Error, analysis, atomic_write,
+    backup::{self, Backup},
+    config::{self, Config},
+    drift, json_style, merge,
+    profiles::{self, ProfileRules, ProfileTarget},
+    validate,
+};
+
+/// The Karabiner-Elements configuration directory: `config_dir` when given, otherwise
+/// `~/.config/karabiner`. It must already exist.
+// https://karabiner-elements.pqrs.org/docs/json/location/
+pub fn config_dir(config_dir: Option<&std::path::Path>) -> crate::Result<std::path::PathBuf> {
+    let config_dir = match config_dir {
+        Some(config_dir) => config_dir.to_path_buf(),
+        None => std::env::var("HOME")
+            .map(std::path::PathBuf::from)
+            .map_err(|_| Error::NoHome)?
+            .join(".config/karabiner"),
+    };
+    if !config_dir.is_dir() {
+        return Err(Error::ConfigDirMissing(config_dir));
+    }
+    Ok(config_dir)
+}
+
+/// Reads karabiner.json, returning its text (for `json_style`) and its parsed contents.
+pub fn read_karabiner_json(path: &std::path::Path) -> crate::Result<(String, serde_json::Value)> {
+    let text = std::fs::read_to_string(path).map_err(|source| match source.kind() {
+        std::io::ErrorKind::NotFound => Error::KarabinerJsonMissing(path.to_path_buf()),
+        _ => Error::Io {
+            path: path.to_path_buf(),
+            source,
+        },
+    })?;
+    let karabiner_json =
+        serde_json::from_str(&text).map_err(|source| Error::InvalidKarabinerJson {
+            path: path.to_path_buf(),
+            source,
+        })?;
+    Ok((text, karabiner_json))
+}
+
+/// What to install and where.
+///
+/// ```ignore
+/// let installer = Installer {
+///     profiles: vec!["Work".to_string()],
+///     ..Installer::new(config)
+/// };
+/// karaconf::install::install(&installer)?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct Installer {
+    pub config: Config,
+    /// Karabiner-Elements configuration directory (default: ~/.config/karabiner).
+    pub config_dir: Option<std::path::PathBuf>,
+    /// File name of the copy written to <config-dir>/assets/complex_modifications.
+    pub assets_file_name: String,
+    /// Where else custom.json is written, e.g. into the repository holding the rule sets.
+    pub output: Option<std::path::PathBuf>,
+    /// Install into these profiles instead of the ones in `config`. Missing profiles are created.
+    pub profiles: Vec<String>,
+    /// Overwrite owned rules edited outside karaconf instead of failing with `Error::Drifted`.
+    pub force: bool,
+    /// Fail with `Error::Conflicts` instead of installing rules with conflicting bindings (see
+    /// `analysis::conflicts`).
+    pub deny_conflicts: bool,
+    /// Number of karabiner.json backups to keep.
+    pub keep_backups: usize,
+}
+
+impl Installer {
+    pub fn new(config: Config) -> Self {
+        Installer {
+            config,
+            config_dir: None,
+            assets_file_name: "custom.json".to_string(),
+            output: None,
+            profiles: vec![],
+            force: false,
+            deny_conflicts: false,
+            keep_backups: 10,
+        }
+    }
+
+    pub fn karabiner_json(&self) -> crate::Result<std::path::PathBuf> {
+        Ok(config_dir(self.config_dir.as_deref())?.join("karabiner.json"))
+    }
+
+    pub fn karabiner_assets(&self) -> crate::Result<std::path::PathBuf> {
+        Ok(config_dir(self.config_dir.as_deref())?
+            .join("assets/complex_modifications")
+            .join(&self.assets_file_name))
+    }
+
+    fn targets(&self) -> Vec<ProfileRules> {
+        if self.profiles.is_empty() {
+            return self.config.profiles.clone();
+        }
+        self.profiles
+            .iter()
+            .map(|name| {
+                let target = ProfileTarget::Named(name.clone());
+                let rules = self.config.rules_for(&target);
+                ProfileRules { target, rules }
+            })
+            .collect()
+    }
+}
+
+/// Everything an install writes, computed in memory.
+#[derive(Debug)]
+pub struct Plan {
+    pub output_path: Option<std::path::PathBuf>,
+    pub custom_json: Vec<u8>,
+    pub karabiner_assets_path: std::path::PathBuf,
+    pub karabiner_json_path: std::path::PathBuf,
+    pub karabiner_json: serde_json::Value,
+    pub karabiner_json_style: json_style::JsonStyle,
+    pub state_path: std::path::PathBuf,
+    pub state: drift::InstallState,
+    pub reports: Vec<ProfileReport>,
+    force: bool,
+    keep_backups: usize,
+}
+
+#[derive(Debug)]
+pub struct ProfileReport {
+    pub target: ProfileTarget,
+    pub rules: usize,
+    pub created: bool,
+    pub replaced: usize,
+    /// Descriptions of rules without the `[karaconf] ` prefix that were replaced (see
+    /// `merge::MergedRules::taken_over`).
+    pub taken_over: Vec<String>,
+    pub kept: Vec<String>,
+    /// Owned rules that were edited outside karaconf since the last install.
+    pub drifted: Vec<serde_json::Value>,
+    /// Descriptions of owned rules that were deleted outside karaconf since the last install.
+    pub deleted: Vec<String>,
+    /// What this profile's owned rules become.
+    pub owned: Vec<serde_json::Value>,
+}
+
+#[derive(Debug)]
+pub struct Installed {
+    /// Copy of karabiner.json taken before it was replaced, if there was one.
+    pub backup: Option<Backup>,
+    pub reports: Vec<ProfileReport>,
+}
+
+/// Computes what `install` would write without touching any file.
+pub fn plan(installer: &Installer) -> crate::Result<Plan> {
+    let targets = installer.targets();
+    let complex_modifications = config::generate(&installer.config);
+    let invalid = validate::validate_rules(&complex_modifications.rules);
+    if !invalid.is_empty() {
+        return Err(Error::Invalid(invalid));
+    }
+    if installer.deny_conflicts {
+        let rules = complex_modifications
+            .rules
+            .iter()
+            .map(|rule| (rule.description.clone(), rule.manipulators.clone()))
+            .collect::<Vec<_>>();
+        let conflicts = analysis::conflicts(&rules);
+        if !conflicts.is_empty() {
+            return Err(Error::Conflicts(conflicts));
+        }
+    }
+
+    // 1. custom.json, and its copy in karabiner assets (~/.config/karabiner/assets/complex_modifications/custom.json)
+    let custom_json = serde_json::to_vec_pretty(&complex_modifications).map_err(Error::Json)?;
+    let karabiner_assets_path = installer.karabiner_assets()?;
+
+    // 2. karabiner.json (~/.config/karabiner/karabiner.json)
+    let karabiner_json_path = installer.karabiner_json()?;
+    // Parsed before anything is written, so a broken karabiner.json leaves every file untouched.
+    // Key order and indentation are kept so that only the `rules` arrays change on disk.
+    let (karabiner_json_text, mut karabiner_json) = read_karabiner_json(&karabiner_json_path)?;
+    let karabiner_json_style = json_style::JsonStyle::detect(&karabiner_json_text);
+    let mut state = drift::InstallState::load(&karabiner_json_path)?;
+    let mut reports = vec![];
+    for profile in targets {
+        let name = profiles::profile_name(&karabiner_json, &profile.target);
+        let (complex_modifications_json, created) = profiles::complex_modifications_mut(
+            &mut karabiner_json,
+            &karabiner_json_path,
+            &profile.target,
+        )?;
+        let existing_rules = match complex_modifications_json.get("rules") {
+            Some(rules) => rules
+                .as_array()
+                .ok_or_else(|| Error::UnexpectedKarabinerJson {
+                    path: karabiner_json_path.clone(),
+                    what: format!("complex_modifications.rules of {}", profile.target),
+                    expected: "an array".to_string(),
+                })?
+                .clone(),
+            None => vec![],
+        };
+        let drifted = state.drifted(&name, &existing_rules);
+        let deleted = state.deleted(&name, &existing_rules);
+        let merged = merge::merge_rules(&existing_rules, &profile.rules)?;
+        state.record(&name, &merged.rules);
+        complex_modifications_json
+            .insert("rules".to_string(), serde_json::Value::Array(merged.rules));
+        reports.push(ProfileReport {
+            target: profile.target,
+            rules: profile.rules.len(),
+            created,
+            replaced: merged.replaced,
+            taken_over: merged.taken_over,
+            kept: merged.kept,
+            drifted,
+            deleted,
+            owned: profile
+                .rules
+                .iter()
+                .map(serde_json::to_value)
+                .collect::<Result<_, _>>()
+                .map_err(Error::Json)?,
+        });
+    }
+
+    Ok(Plan {
+        output_path: installer.output.clone(),
+        custom_json,
+        karabiner_assets_path,
+        state_path: drift::state_path(&karabiner_json_path),
+        karabiner_json_path,
+        karabiner_json,
+        karabiner_json_style,
+        state,
+        reports,
+        force: installer.force,
+        keep_backups: installer.keep_backups,
+    })
+}
+
+impl Plan {
+    /// karabiner.json as it will be written.
+    pub fn karabiner_json_data(&self) -> crate::Result<Vec<u8>> {
+        self.karabiner_json_style.to_vec(&self.karabiner_json)
+    }
+
+    /// Backs up karabiner.json and writes every file of the plan atomically.
+    pub fn write(self) -> crate::Result<Installed> {
+        let drifted = self
+            .reports
+            .iter()
+            .flat_map(|report| {
+                report
+                    .drifted
+                    .iter()
+                    .map(|rule| rule["description"].as_str().unwrap_or_default())
+                    .chain(report.deleted.iter().map(String::as_str))
+                    .map(|description| (report.target.clone(), description.to_string()))
+            })
+            .collect::<Vec<_>>();
+        if !drifted.is_empty() && !self.force {
+            return Err(Error::Drifted { rules: drifted });
+        }
+
+        let karabiner_json_data = self.karabiner_json_data()?;
+        let state_data = serde_json::to_vec_pretty(&self.state).map_err(Error::Json)?;
+        let backup = backup::backup(&self.karabiner_json_path, self.keep_backups)?;
+        if let Some(assets_dir) = self.karabiner_assets_path.parent() {
+            std::fs::create_dir_all(assets_dir).map_err(Error::io(assets_dir))?;
+        }
+        let mut files = vec![
+            (self.karabiner_assets_path.as_path(), &self.custom_json[..]),
+            (self.karabiner_json_path.as_path(), &karabiner_json_data[..]),
+            (self.state_path.as_path(), &state_data[..]),
+        ];
+        if let Some(output_path) = &self.output_path {
+            files.insert(0, (output_path.as_path(), &self.custom_json[..]));
+        }
+        atomic_write::write_all(&files)?;
+        Ok(Installed {
+            backup,
+            reports: self.reports,
+        })
+    }
+}
+
+/// Writes custom.json and its assets copy, and replaces the owned rules in karabiner.json.
+pub fn install(installer: &Installer) -> crate::Result<Installed> {
+    plan(installer)?.write()
+}
@@ -0,0 +1,49 @@
+/// How an existing JSON file is laid out, so it can be written back without reformatting the
+/// parts karaconf does not touch. Karabiner writes karabiner.json with 4-space indentation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonStyle {
+    pub indent: String,
+    pub trailing_newline: bool,
+}
+
+impl Default for JsonStyle {
+    fn default() -> Self {
+        JsonStyle {
+            indent: "    ".to_string(),
+            trailing_newline: false,
+        }
+    }
+}
+
+impl JsonStyle {
+    /// Takes the indentation of the first indented line, falling back to the defaults for empty
+    /// or single-line documents.
+    pub fn detect(text: &str) -> Self {
+        let indent = text
+            .lines()
+            .skip(1)
+            .map(|line| &line[..line.len() - line.trim_start().len()])
+            .find(|indent| !indent.is_empty());
+        match indent {
+            Some(indent) => JsonStyle {
+                indent: indent.to_string(),
+                trailing_newline: text.ends_with('\n'),
+            },
+            None => JsonStyle::default(),
+        }
+    }
+
+    /// Pretty-prints `value` in this style. Object keys keep the order they were parsed in.
+    pub fn to_vec<T: serde::Serialize>(&self, value: &T) -> crate::Result<Vec<u8>> {
+        let mut data = vec![];
+        let formatter = serde_json::ser::PrettyFormatter::with_indent(self.indent.as_bytes());
+        let mut serializer = serde_json::Serializer::with_formatter(&mut data, formatter);
+        value
+            .serialize(&mut serializer)
+            .map_err(crate::Error::Json)?;
+        if self.trailing_newline {
+            data.push(b'\n');
+        }
+        Ok(data)
+    }
+}
@@ -1,38 +1,86 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+#[serde(from = "String", into = "String")]
 pub enum BundleIdentifier {
-    #[serde(rename = "com.googlecode.iterm2")]
     ITerm2,
-    #[serde(rename = "com.microsoft.VSCode")]
     VSCode,
-    #[serde(rename = "io.dynalist")]
     Dynalist, // https://help.dynalist.io/article/91-keyboard-shortcut-reference
-    #[serde(rename = "com.tinyspeck.slackmacgap")]
     Slack,
-    #[serde(rename = "com.google.Chrome")]
     GoogleChrome,
-    #[serde(rename = "notion.id")]
     Notion,
-    #[serde(rename = "com.openai.chat")]
     ChatGPT,
+    Other(String),
+}
+
+impl BundleIdentifier {
+    pub fn as_str(&self) -> &str {
+        match self {
+            BundleIdentifier::ITerm2 => "com.googlecode.iterm2",
+            BundleIdentifier::VSCode => "com.microsoft.VSCode",
+            BundleIdentifier::Dynalist => "io.dynalist",
+            BundleIdentifier::Slack => "com.tinyspeck.slackmacgap",
+            BundleIdentifier::GoogleChrome => "com.google.Chrome",
+            BundleIdentifier::Notion => "notion.id",
+            BundleIdentifier::ChatGPT => "com.openai.chat",
+            BundleIdentifier::Other(bundle_identifier) => bundle_identifier,
+        }
+    }
+}
+
+impl std::convert::From<String> for BundleIdentifier {
+    fn from(bundle_identifier: String) -> Self {
+        [
+            BundleIdentifier::ITerm2,
+            BundleIdentifier::VSCode,
+            BundleIdentifier::Dynalist,
+            BundleIdentifier::Slack,
+            BundleIdentifier::GoogleChrome,
+            BundleIdentifier::Notion,
+            BundleIdentifier::ChatGPT,
+        ]
+        .into_iter()
+        .find(|known| known.as_str() == bundle_identifier)
+        .unwrap_or(BundleIdentifier::Other(bundle_identifier))
+    }
+}
+
+impl std::convert::From<BundleIdentifier> for String {
+    fn from(bundle_identifier: BundleIdentifier) -> Self {
+        bundle_identifier.as_str().to_string()
+    }
 }
 
 // https://karabiner-elements.pqrs.org/docs/json/root-data-structure/#custom-json-file-in-configkarabinerassetscomplex_modifications
-#[derive(Debug, serde::Serialize)]
-pub struct ComplexModifications<'a> {
-    pub title: &'a str,
-    pub rules: &'a Vec<Rule>,
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ComplexModifications {
+    pub title: String,
+    pub rules: Vec<Rule>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Rule {
     pub description: String,
     pub manipulators: Vec<Manipulator>,
+
+    /// Fields karaconf does not model, kept so that rules read from an existing file are written back unchanged.
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Rule {
+    pub fn new(description: impl Into<String>, manipulators: Vec<Manipulator>) -> Rule {
+        Rule {
+            description: description.into(),
+            manipulators,
+            extra: serde_json::Map::new(),
+        }
+    }
 }
 
-#[derive(Debug, Clone, Serialize)]
-#[serde(untagged)]
+/// Conditions that do not fit one of the typed variants exactly (e.g. `device_if`) are kept as `Other`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
 pub enum Condition {
     OnApplication {
         r#type: ConditionType,
@@ -43,13 +91,16 @@ pub enum Condition {
         name: VirtualKey,
         value: u8,
     },
+    Other(serde_json::Value),
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum ConditionType {
     FrontmostApplicationIf,
+    FrontmostApplicationUnless,
     VariableIf,
+    VariableUnless,
 }
 
 impl Condition {
@@ -85,28 +136,179 @@ impl Condition {
     }
 }
 
-#[derive(Debug, Serialize)]
+/// Keys of the `from` events, matching the variants of `FromEvent`.
+pub(crate) const FROM_EVENT_KEYS: &[&str] = &[
+    "key_code",
+    "consumer_key_code",
+    "pointing_button",
+    "any",
+    "simultaneous",
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "serde_json::Map<String, serde_json::Value>")]
 pub struct From {
-    pub key_code: KeyCode,
-
-    #[serde(skip_serializing_if = "Option::is_none")]
+    /// `None` for `"from": {}` (e.g. in a hand-edited karabiner.json), which `validate` rejects,
+    /// and for an event karaconf cannot parse (e.g. a key code it does not know), which is kept
+    /// in `extra` instead.
+    #[serde(flatten)]
+    pub event: Option<FromEvent>,
+
+    /// `None` without modifiers, and for modifiers karaconf cannot parse (e.g. with a field it
+    /// does not know), which are kept in `extra` instead.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub modifiers: Option<FromModifier>,
+
+    /// Fields karaconf does not model (e.g. `simultaneous_options`).
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl From {
+    /// Returns the key code of a `key_code` event, or `None` for the other event kinds.
+    pub fn key_code(&self) -> Option<&KeyCode> {
+        match &self.event {
+            Some(FromEvent::KeyCode(key_code)) => Some(key_code),
+            _ => None,
+        }
+    }
+}
+
+impl TryFrom<serde_json::Map<String, serde_json::Value>> for From {
+    type Error = serde_json::Error;
+
+    fn try_from(map: serde_json::Map<String, serde_json::Value>) -> Result<Self, Self::Error> {
+        let mut event = None;
+        let mut modifiers = None;
+        let mut extra = serde_json::Map::new();
+        for (key, value) in map {
+            if event.is_none() && FROM_EVENT_KEYS.contains(&key.as_str()) {
+                let mut single = serde_json::Map::new();
+                single.insert(key.clone(), value.clone());
+                if let Ok(parsed) = serde_json::from_value(serde_json::Value::Object(single)) {
+                    event = Some(parsed);
+                    continue;
+                }
+            } else if modifiers.is_none() && key == "modifiers" {
+                if let Ok(parsed) = serde_json::from_value(value.clone()) {
+                    modifiers = Some(parsed);
+                    continue;
+                }
+            }
+            extra.insert(key, value);
+        }
+        Ok(From {
+            event,
+            modifiers,
+            extra,
+        })
+    }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
+pub enum FromEvent {
+    KeyCode(KeyCode),
+    ConsumerKeyCode(String),
+    PointingButton(PointingButton),
+    Any(String),
+    Simultaneous(Vec<serde_json::Value>),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "FromModifierRepr", into = "FromModifierRepr")]
 pub enum FromModifier {
     Optional(Vec<ModifierKey>),
     Mandatory(Vec<ModifierKey>),
+    MandatoryAndOptional {
+        mandatory: Vec<ModifierKey>,
+        optional: Vec<ModifierKey>,
+    },
+}
+
+impl FromModifier {
+    pub fn mandatory(&self) -> &[ModifierKey] {
+        match self {
+            FromModifier::Optional(_) => &[],
+            FromModifier::Mandatory(mandatory)
+            | FromModifier::MandatoryAndOptional { mandatory, .. } => mandatory,
+        }
+    }
+
+    pub fn optional(&self) -> &[ModifierKey] {
+        match self {
+            FromModifier::Mandatory(_) => &[],
+            FromModifier::Optional(optional)
+            | FromModifier::MandatoryAndOptional { optional, .. } => optional,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FromModifierRepr {
+    #[serde(
+        default,
+        deserialize_with = "optional_one_or_many",
+        skip_serializing_if = "Option::is_none"
+    )]
+    mandatory: Option<Vec<ModifierKey>>,
+
+    #[serde(
+        default,
+        deserialize_with = "optional_one_or_many",
+        skip_serializing_if = "Option::is_none"
+    )]
+    optional: Option<Vec<ModifierKey>>,
+}
+
+impl TryFrom<FromModifierRepr> for FromModifier {
+    type Error = &'static str;
+
+    fn try_from(repr: FromModifierRepr) -> Result<Self, Self::Error> {
+        match (repr.mandatory, repr.optional) {
+            (Some(mandatory), Some(optional)) => Ok(FromModifier::MandatoryAndOptional {
+                mandatory,
+                optional,
+            }),
+            (Some(mandatory), None) => Ok(FromModifier::Mandatory(mandatory)),
+            (None, Some(optional)) => Ok(FromModifier::Optional(optional)),
+            (None, None) => Err("from.modifiers must have `mandatory` or `optional`"),
+        }
+    }
+}
+
+impl std::convert::From<FromModifier> for FromModifierRepr {
+    fn from(modifier: FromModifier) -> Self {
+        match modifier {
+            FromModifier::Optional(optional) => FromModifierRepr {
+                mandatory: None,
+                optional: Some(optional),
+            },
+            FromModifier::Mandatory(mandatory) => FromModifierRepr {
+                mandatory: Some(mandatory),
+                optional: None,
+            },
+            FromModifier::MandatoryAndOptional {
+                mandatory,
+                optional,
+            } => FromModifierRepr {
+                mandatory: Some(mandatory),
+                optional: Some(optional),
+            },
+        }
+    }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct SetVariable {
     pub name: VirtualKey,
     pub value: u8,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct MouseKey {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub x: Option<i32>,
@@ -118,15 +320,16 @@ pub struct MouseKey {
     pub vertical_wheel: Option<i32>,
 }
 
-#[derive(Debug, Serialize)]
-#[serde(untagged, rename_all = "snake_case")]
+/// Events that do not fit one of the typed variants exactly (e.g. `key_code` with `repeat`) are kept as `Other`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
 pub enum To {
     Variable {
         set_variable: SetVariable,
     },
     Key {
         key_code: KeyCode,
-        #[serde(skip_serializing_if = "Option::is_none")]
+        #[serde(default, skip_serializing_if = "Option::is_none")]
         modifiers: Option<Vec<ModifierKey>>,
     },
     Mouse {
@@ -136,75 +339,160 @@ pub enum To {
         pointing_button: PointingButton,
     },
     Command {
-        shell_command: &'static str,
+        shell_command: String,
     },
+    Other(serde_json::Value),
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum PointingButton {
     Button1,
     Button2,
+    Button3,
+    Button4,
+    Button5,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Manipulator {
     pub r#type: ManipulatorType,
 
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub conditions: Option<Vec<Condition>>,
 
     pub from: From,
 
-    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(
+        default,
+        deserialize_with = "one_or_many",
+        skip_serializing_if = "Vec::is_empty"
+    )]
     pub to: Vec<To>,
 
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub to_after_key_up: Option<Vec<ToAfterKeyUp>>,
-
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub to_if_alone: Option<Vec<ToIfAlone>>,
+    #[serde(
+        default,
+        deserialize_with = "optional_one_or_many",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub to_after_key_up: Option<Vec<To>>,
+
+    #[serde(
+        default,
+        deserialize_with = "optional_one_or_many",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub to_if_alone: Option<Vec<To>>,
+
+    /// Fields karaconf does not model (e.g. `parameters`, `to_if_held_down`, `to_delayed_action`).
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
 }
 
 impl Manipulator {
-    pub fn builder() -> ManipulatorInitBuilder {
-        ManipulatorInitBuilder::default()
+    /// Starts a manipulator. `build()` is only available once the from-event is set, and the
+    /// from-event can be set only once:
+    ///
+    /// ```compile_fail
+    /// use karaconf::karabiner_data::{KeyCode, Manipulator};
+    /// Manipulator::builder().to_key(KeyCode::A, None).build();
+    /// ```
+    ///
+    /// ```compile_fail
+    /// use karaconf::karabiner_data::{KeyCode, Manipulator};
+    /// Manipulator::builder().from_key(KeyCode::A).from_key(KeyCode::B);
+    /// ```
+    pub fn builder() -> ManipulatorInitBuilder<NoFrom> {
+        ManipulatorInitBuilder {
+            conditions: None,
+            from: NoFrom,
+            to: vec![],
+            to_after_key_up: None,
+            to_if_alone: None,
+        }
+    }
+}
+
+/// Reads a list that Karabiner also accepts as a single item (e.g. `"to": {...}` or
+/// `"optional": "any"`), which is written back as a list of one.
+fn one_or_many<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany<T> {
+        Many(Vec<T>),
+        One(T),
     }
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::Many(items) => items,
+        OneOrMany::One(item) => vec![item],
+    })
+}
+
+fn optional_one_or_many<'de, D, T>(deserializer: D) -> Result<Option<Vec<T>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    one_or_many(deserializer).map(Some)
 }
 
-#[derive(Default)]
-pub struct ManipulatorInitBuilder {
+/// State of a `ManipulatorInitBuilder` whose from-event is not set yet.
+pub struct NoFrom;
+
+/// Builds a `Manipulator`; `F` is `NoFrom` until `from_key` or `from_key_with_modifiers` is
+/// called, and `From` afterwards.
+pub struct ManipulatorInitBuilder<F> {
     conditions: Option<Vec<Condition>>,
-    from: Option<From>,
+    from: F,
     to: Vec<To>,
-    to_after_key_up: Option<Vec<ToAfterKeyUp>>,
-    to_if_alone: Option<Vec<ToIfAlone>>,
+    to_after_key_up: Option<Vec<To>>,
+    to_if_alone: Option<Vec<To>>,
 }
 
-impl ManipulatorInitBuilder {
-    pub fn condition(mut self, condition: Condition) -> Self {
-        self.conditions.get_or_insert(vec![]).push(condition);
-        self
+impl ManipulatorInitBuilder<NoFrom> {
+    pub fn from_key(self, key_code: KeyCode) -> ManipulatorInitBuilder<From> {
+        self.with_from(From {
+            event: Some(FromEvent::KeyCode(key_code)),
+            modifiers: None,
+            extra: serde_json::Map::new(),
+        })
     }
 
-    pub fn conditions(mut self, conditions: Vec<Condition>) -> Self {
-        self.conditions = Some(conditions);
-        self
+    pub fn from_key_with_modifiers(
+        self,
+        key_code: KeyCode,
+        modifiers: FromModifier,
+    ) -> ManipulatorInitBuilder<From> {
+        self.with_from(From {
+            event: Some(FromEvent::KeyCode(key_code)),
+            modifiers: Some(modifiers),
+            extra: serde_json::Map::new(),
+        })
     }
 
-    pub fn from_key(mut self, key_code: KeyCode) -> Self {
-        self.from = Some(From {
-            key_code,
-            modifiers: None,
-        });
+    fn with_from(self, from: From) -> ManipulatorInitBuilder<From> {
+        ManipulatorInitBuilder {
+            conditions: self.conditions,
+            from,
+            to: self.to,
+            to_after_key_up: self.to_after_key_up,
+            to_if_alone: self.to_if_alone,
+        }
+    }
+}
+
+impl<F> ManipulatorInitBuilder<F> {
+    pub fn condition(mut self, condition: Condition) -> Self {
+        self.conditions.get_or_insert(vec![]).push(condition);
         self
     }
 
-    pub fn from_key_with_modifiers(mut self, key_code: KeyCode, modifiers: FromModifier) -> Self {
-        self.from = Some(From {
-            key_code,
-            modifiers: Some(modifiers),
-        });
+    pub fn conditions(mut self, conditions: Vec<Condition>) -> Self {
+        self.conditions = Some(conditions);
         self
     }
 
@@ -216,9 +504,9 @@ impl ManipulatorInitBuilder {
         self
     }
 
-    pub fn to_command(mut self, command: &'static str) -> Self {
+    pub fn to_command(mut self, command: impl Into<String>) -> Self {
         self.to.push(To::Command {
-            shell_command: command,
+            shell_command: command.into(),
         });
         self
     }
@@ -241,59 +529,107 @@ impl ManipulatorInitBuilder {
     pub fn to_after_key_up(mut self, set_variable: SetVariable) -> Self {
         self.to_after_key_up
             .get_or_insert(vec![])
-            .push(ToAfterKeyUp { set_variable });
+            .push(To::Variable { set_variable });
         self
     }
 
     pub fn to_if_alone(mut self, key_code: KeyCode) -> Self {
-        self.to_if_alone
-            .get_or_insert(vec![])
-            .push(ToIfAlone { key_code });
+        self.to_if_alone.get_or_insert(vec![]).push(To::Key {
+            key_code,
+            modifiers: None,
+        });
         self
     }
+}
 
+impl ManipulatorInitBuilder<From> {
     pub fn build(self) -> Manipulator {
         Manipulator {
             r#type: ManipulatorType::Basic,
             conditions: self.conditions,
-            from: self.from.unwrap_or(From {
-                key_code: KeyCode::Escape,
-                modifiers: None,
-            }),
+            from: self.from,
             to: self.to,
             to_after_key_up: self.to_after_key_up,
             to_if_alone: self.to_if_alone,
+            extra: serde_json::Map::new(),
         }
     }
 }
 
-#[derive(Debug, Serialize, Default)]
-#[serde(rename_all = "snake_case")]
+/// Types karaconf does not generate (e.g. `mouse_motion_to_scroll`) are kept as `Other`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(from = "String", into = "String")]
 pub enum ManipulatorType {
     #[default]
     Basic,
+    Other(String),
 }
 
-#[derive(Debug, Serialize)]
-pub struct ToIfAlone {
-    pub key_code: KeyCode,
+impl ManipulatorType {
+    pub fn as_str(&self) -> &str {
+        match self {
+            ManipulatorType::Basic => "basic",
+            ManipulatorType::Other(name) => name,
+        }
+    }
 }
 
-#[derive(Debug, Serialize)]
-pub struct ToAfterKeyUp {
-    pub set_variable: SetVariable,
+impl std::convert::From<String> for ManipulatorType {
+    fn from(name: String) -> Self {
+        match name.as_str() {
+            "basic" => ManipulatorType::Basic,
+            _ => ManipulatorType::Other(name),
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Clone)]
-#[serde(rename_all = "snake_case")]
+impl std::convert::From<ManipulatorType> for String {
+    fn from(manipulator_type: ManipulatorType) -> Self {
+        manipulator_type.as_str().to_string()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+#[serde(from = "String", into = "String")]
 pub enum VirtualKey {
     Vk1,
     Vk2,
     Vk3,
     Vk4,
+    Other(String),
+}
+
+impl VirtualKey {
+    pub fn as_str(&self) -> &str {
+        match self {
+            VirtualKey::Vk1 => "vk1",
+            VirtualKey::Vk2 => "vk2",
+            VirtualKey::Vk3 => "vk3",
+            VirtualKey::Vk4 => "vk4",
+            VirtualKey::Other(name) => name,
+        }
+    }
+}
+
+impl std::convert::From<String> for VirtualKey {
+    fn from(name: String) -> Self {
+        match name.as_str() {
+            "vk1" => VirtualKey::Vk1,
+            "vk2" => VirtualKey::Vk2,
+            "vk3" => VirtualKey::Vk3,
+            "vk4" => VirtualKey::Vk4,
+            _ => VirtualKey::Other(name),
+        }
+    }
 }
 
-#[derive(Debug, Serialize)]
+impl std::convert::From<VirtualKey> for String {
+    fn from(virtual_key: VirtualKey) -> Self {
+        virtual_key.as_str().to_string()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
 #[serde(rename_all = "snake_case")]
 pub enum ModifierKey {
     Any,
@@ -304,9 +640,27 @@ pub enum ModifierKey {
     Opt,
     #[serde(rename = "command")]
     Cmd,
+    CapsLock,
+    LeftCommand,
+    LeftControl,
+    LeftOption,
+    LeftShift,
+    RightCommand,
+    RightControl,
+    RightOption,
+    RightShift,
+    Fn,
+    /// Same as `LeftOption`.
+    LeftAlt,
+    /// Same as `LeftCommand`.
+    LeftGui,
+    /// Same as `RightOption`.
+    RightAlt,
+    /// Same as `RightCommand`.
+    RightGui,
 }
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
 #[serde(rename_all = "snake_case")]
 #[allow(dead_code)]
 // See https://github.com/pqrs-org/Karabiner-Elements/blob/a9154a6b073a3396631f43ed11f6dc603c28ea7b/src/share/types/key_code.hpp#L146-L360
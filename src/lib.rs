@@ -1,2 +1,18 @@
+pub mod analysis;
+pub mod atomic_write;
+pub mod backup;
+pub mod config;
+pub mod diff;
+pub mod drift;
+pub mod error;
+pub mod import;
+pub mod install;
+pub mod json_style;
 pub mod karabiner_data;
+pub mod merge;
+pub mod profiles;
 pub mod rule_sets;
+pub mod simulator;
+pub mod validate;
+
+pub use error::{Error, Result};
@@ -1,79 +1,884 @@
-pub mod karabiner_data;
-pub mod rule_sets;
-
-use std::io::Seek as _;
+use clap::Parser as _;
+use karaconf::{
+    analysis, atomic_write, backup,
+    config::{self, Config},
+    diff, drift, import,
+    install::{self, Installer, ProfileReport},
+    karabiner_data::{self, KeyCode, ModifierKey},
+    merge,
+    rule_sets::{self, RuleSet, Selection},
+    simulator::{KeyEvent, Mismatch, Output, Simulator},
+    validate,
+};
 
 const CUSTOM_JSON_FILENAME: &str = "custom.json";
+/// custom.json of this repository, wherever the binary is run from.
+const DEFAULT_OUTPUT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/custom.json");
+const DEFAULT_RULE_SETS_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/rule_sets");
+const DEFAULT_RULE_SETS_FILE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/rule_sets.json");
+
+#[derive(clap::Parser)]
+#[command(
+    about = "Update the Karabiner-Elements configuration from the rule sets in this repository",
+    after_help = "Exit status: 0 on success, 1 when `diff` finds changes or `check` finds problems, 2 on errors."
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    install: InstallArgs,
+
+    #[command(flatten)]
+    paths: Paths,
+
+    #[command(flatten)]
+    rule_sets: RuleSetArgs,
+
+    /// Number of karabiner.json backups to keep in <config-dir>/karaconf_backups
+    #[arg(long, global = true, default_value_t = 10)]
+    keep_backups: usize,
+}
+
+#[derive(clap::Args)]
+struct InstallArgs {
+    /// Print the changes instead of writing any file, like `diff`
+    #[arg(long)]
+    dry_run: bool,
+
+    /// With --dry-run, print a unified text diff instead of the rule-level summary
+    #[arg(long, requires = "dry_run")]
+    text: bool,
+
+    /// Overwrite karaconf rules that were edited outside karaconf (e.g. in the Karabiner UI)
+    /// since the last install, instead of refusing
+    #[arg(long)]
+    force: bool,
+
+    #[command(flatten)]
+    targets: TargetArgs,
+}
+
+impl InstallArgs {
+    /// Whether any flag is given, which is an error before a subcommand since it would be ignored.
+    fn is_set(&self) -> bool {
+        self.dry_run || self.text || self.force || !self.targets.profiles.is_empty()
+    }
+}
+
+#[derive(clap::Args)]
+struct TargetArgs {
+    /// Install into this profile instead of the ones configured in `config::profile_rules()`
+    /// (repeatable).
+    /// Missing profiles are created.
+    #[arg(long = "profile")]
+    profiles: Vec<String>,
+}
+
+#[derive(clap::Args)]
+struct RuleSetArgs {
+    /// JSON file choosing rule sets by name, e.g. {"enabled": ["virtual_key", "vk1"], "disabled": []}.
+    /// A missing file uses every rule set.
+    #[arg(long, global = true, env = "KARACONF_RULE_SETS_FILE", default_value = DEFAULT_RULE_SETS_FILE)]
+    rule_sets_file: std::path::PathBuf,
+
+    /// Use only these rule sets, in this order (repeatable; overrides "enabled" of the file)
+    #[arg(long = "rule-set", global = true)]
+    enabled: Vec<String>,
+
+    /// Leave out this rule set (repeatable)
+    #[arg(long = "disable-rule-set", global = true)]
+    disabled: Vec<String>,
+
+    /// Put more specific manipulators (app conditions, then variables, then mandatory modifiers)
+    /// first, so new rule sets need no hand-picked position
+    #[arg(long, global = true)]
+    sort_by_specificity: bool,
+}
+
+impl RuleSetArgs {
+    /// The selected rule sets, in evaluation order.
+    fn select(&self) -> anyhow::Result<Vec<Box<dyn RuleSet>>> {
+        let selection = Selection::load(&self.rule_sets_file)?.overridden_by(Selection {
+            enabled: self.enabled.clone(),
+            disabled: self.disabled.clone(),
+            sort_by_specificity: self.sort_by_specificity,
+        });
+        Ok(rule_sets::select(rule_sets::registry(), &selection)?)
+    }
+}
+
+#[derive(clap::Args)]
+struct Paths {
+    /// Karabiner-Elements configuration directory (default: ~/.config/karabiner)
+    #[arg(long, global = true, env = "KARACONF_CONFIG_DIR")]
+    config_dir: Option<std::path::PathBuf>,
+
+    /// File name of the copy written to <config-dir>/assets/complex_modifications
+    #[arg(long, global = true, env = "KARACONF_ASSETS_FILE_NAME", default_value = CUSTOM_JSON_FILENAME)]
+    assets_file_name: String,
+
+    /// Where the generated custom.json is written
+    #[arg(long, global = true, env = "KARACONF_OUTPUT", default_value = DEFAULT_OUTPUT)]
+    output: std::path::PathBuf,
+}
+
+impl Paths {
+    fn config_dir(&self) -> anyhow::Result<std::path::PathBuf> {
+        Ok(install::config_dir(self.config_dir.as_deref())?)
+    }
+
+    fn karabiner_json(&self) -> anyhow::Result<std::path::PathBuf> {
+        Ok(self.config_dir()?.join("karabiner.json"))
+    }
 
-fn main() -> anyhow::Result<()> {
-    let rules = vec![karabiner_data::Rule {
-        description: "Personal rules".to_string(),
-        manipulators: vec![
-            rule_sets::virtual_key::manipulators(),
-            rule_sets::iterm2::manipulators(),
-            rule_sets::vscode::manipulators(),
-            rule_sets::dynalist::manipulators(),
-            rule_sets::slack::manipulators(),
-            rule_sets::google_chrome::manipulators(),
-            rule_sets::notion::manipulators(),
-            rule_sets::chatgpt::manipulators(),
-            rule_sets::vk1::manipulators(),
-            rule_sets::vk2::manipulators(),
-            rule_sets::open_apps::manipulators(),
-            rule_sets::vk3::manipulators(),
-            rule_sets::semicolon::manipulators(),
-            rule_sets::singlequote::manipulators(),
-            rule_sets::capslock::manipulators(),
-        ]
+    fn installer(&self, config: &Config, profile_names: &[String]) -> Installer {
+        Installer {
+            config_dir: self.config_dir.clone(),
+            assets_file_name: self.assets_file_name.clone(),
+            output: Some(self.output.clone()),
+            profiles: profile_names.to_vec(),
+            ..Installer::new(config.clone())
+        }
+    }
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Print the generated custom.json, or write it to a file
+    Generate {
+        /// File to write instead of stdout
+        file: Option<std::path::PathBuf>,
+    },
+    /// Write custom.json and its assets copy, and update karabiner.json (the default command)
+    Install(InstallArgs),
+    /// Show how `install` would change the files on disk
+    Diff {
+        /// Print a unified text diff instead of the rule-level summary
+        #[arg(long)]
+        text: bool,
+
+        #[command(flatten)]
+        targets: TargetArgs,
+    },
+    /// Validate the generated rules and karabiner.json without writing anything
+    Check {
+        /// Only check that the custom.json at --output matches the rule sets, printing a diff
+        /// when it is stale. Does not need karabiner.json.
+        #[arg(long)]
+        fresh: bool,
+
+        #[command(flatten)]
+        targets: TargetArgs,
+    },
+    /// List the rule sets and the rules each profile receives
+    List,
+    /// Generate rule_sets modules from the rules of an existing karabiner.json
+    Import {
+        /// karabiner.json to read (default: ~/.config/karabiner/karabiner.json)
+        #[arg(long)]
+        karabiner_json: Option<std::path::PathBuf>,
+
+        /// Only import the rules of this profile (default: every profile)
+        #[arg(long)]
+        profile: Option<String>,
+
+        /// Only import karaconf rules that were edited outside karaconf since the last install
+        #[arg(long)]
+        drifted: bool,
+
+        /// Directory the generated modules are written to
+        #[arg(long, default_value = DEFAULT_RULE_SETS_DIR)]
+        rule_sets_dir: std::path::PathBuf,
+    },
+    /// Put a backup of karabiner.json back in place, or list the backups when none is given
+    Restore {
+        /// Restore the most recent backup
+        #[arg(long, conflicts_with = "timestamp")]
+        latest: bool,
+
+        /// Timestamp of the backup to restore, as listed by `restore`
+        timestamp: Option<String>,
+    },
+    /// Show which manipulators react to a key press in a given context, in the order Karabiner
+    /// checks them, which one wins and what it sends
+    Explain {
+        /// Key code of the key pressed, e.g. `y` or `semicolon`
+        key: String,
+
+        /// Bundle identifier of the frontmost application, e.g. com.googlecode.iterm2
+        #[arg(long)]
+        app: Option<String>,
+
+        /// Variables that are set, e.g. vk1=1 (repeatable or comma-separated)
+        #[arg(long, value_delimiter = ',')]
+        vars: Vec<String>,
+
+        /// Modifiers held down, e.g. shift or left_command (repeatable or comma-separated)
+        #[arg(long, value_delimiter = ',')]
+        mods: Vec<String>,
+    },
+}
+
+fn main() -> std::process::ExitCode {
+    let cli = Cli::parse();
+    if cli.command.is_some() && cli.install.is_set() {
+        <Cli as clap::CommandFactory>::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--dry-run, --text, --force and --profile before a subcommand apply only to a plain \
+                 install; pass them after the subcommand instead",
+            )
+            .exit();
+    }
+    match run(cli) {
+        Ok(exit_code) => exit_code,
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            std::process::ExitCode::from(2)
+        }
+    }
+}
+
+fn run(cli: Cli) -> anyhow::Result<std::process::ExitCode> {
+    let paths = &cli.paths;
+    let rule_sets = cli.rule_sets.select()?;
+    let config = &config::config(&rule_sets);
+    let install_args = match cli.command {
+        None => cli.install,
+        Some(Command::Install(install_args)) => install_args,
+        Some(Command::Generate { file }) => {
+            generate(config, file.as_deref())?;
+            return Ok(std::process::ExitCode::SUCCESS);
+        }
+        Some(Command::Diff { text, targets }) => {
+            return Ok(exit_code(!dry_run(config, paths, &targets.profiles, text)?));
+        }
+        Some(Command::Check { fresh, targets }) => {
+            return Ok(exit_code(check(
+                config,
+                &rule_sets,
+                paths,
+                &targets.profiles,
+                fresh,
+            )?));
+        }
+        Some(Command::List) => {
+            list(config, &rule_sets);
+            return Ok(std::process::ExitCode::SUCCESS);
+        }
+        Some(Command::Import {
+            karabiner_json,
+            profile,
+            drifted,
+            rule_sets_dir,
+        }) => {
+            import(
+                paths,
+                karabiner_json,
+                profile.as_deref(),
+                drifted,
+                &rule_sets_dir,
+            )?;
+            return Ok(std::process::ExitCode::SUCCESS);
+        }
+        Some(Command::Restore { latest, timestamp }) => {
+            restore(paths, latest, timestamp.as_deref(), cli.keep_backups)?;
+            return Ok(std::process::ExitCode::SUCCESS);
+        }
+        Some(Command::Explain {
+            key,
+            app,
+            vars,
+            mods,
+        }) => {
+            explain(&rule_sets, &key, app.as_deref(), &vars, &mods)?;
+            return Ok(std::process::ExitCode::SUCCESS);
+        }
+    };
+    if install_args.dry_run {
+        dry_run(
+            config,
+            paths,
+            &install_args.targets.profiles,
+            install_args.text,
+        )?;
+    } else {
+        install(
+            config,
+            paths,
+            &install_args.targets.profiles,
+            install_args.force,
+            cli.keep_backups,
+        )?;
+    }
+    Ok(std::process::ExitCode::SUCCESS)
+}
+
+fn exit_code(success: bool) -> std::process::ExitCode {
+    if success {
+        std::process::ExitCode::SUCCESS
+    } else {
+        std::process::ExitCode::from(1)
+    }
+}
+
+fn import(
+    paths: &Paths,
+    karabiner_json_path: Option<std::path::PathBuf>,
+    profile: Option<&str>,
+    drifted: bool,
+    rule_sets_dir: &std::path::Path,
+) -> anyhow::Result<()> {
+    let karabiner_json_path = match karabiner_json_path {
+        Some(path) => path,
+        None => paths.karabiner_json()?,
+    };
+    let (_, karabiner_json) = install::read_karabiner_json(&karabiner_json_path)?;
+    let mut rules =
+        import::rules_from_karabiner_json(&karabiner_json, &karabiner_json_path, profile)?;
+    let state = drift::InstallState::load(&karabiner_json_path)?;
+    let drifted_rules = karabiner_json["profiles"]
+        .as_array()
         .into_iter()
         .flatten()
-        .collect::<Vec<karabiner_data::Manipulator>>(),
-    }];
-    let complex_modifications = karabiner_data::ComplexModifications {
-        title: "Personal rules",
-        rules: &rules,
+        .filter(|p| profile.is_none() || p["name"].as_str() == profile)
+        .flat_map(|p| {
+            let name = p["name"].as_str().unwrap_or_default();
+            let rules = p["complex_modifications"]["rules"]
+                .as_array()
+                .cloned()
+                .unwrap_or_default();
+            state.drifted(name, &rules)
+        })
+        .collect::<Vec<_>>();
+    // Rules karaconf installed are already rule sets unless they were edited since.
+    rules.retain(|rule| {
+        serde_json::to_value(rule).is_ok_and(|rule| drifted_rules.contains(&rule))
+            || !drifted && !merge::is_owned_description(&rule.description)
+    });
+    let taken = std::fs::read_dir(rule_sets_dir)
+        .map_err(|source| karaconf::Error::Io {
+            path: rule_sets_dir.to_path_buf(),
+            source,
+        })?
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            Some(path.file_stem()?.to_str()?.to_string())
+        })
+        .collect::<Vec<String>>();
+    let rule_sets = import::rule_sets_from_rules(&rules, &taken);
+    import::write_rule_sets(rule_sets_dir, &rule_sets)?;
+
+    println!(
+        "Imported {} rule(s) from {:?}. Add them to registry() in src/rule_sets/mod.rs:",
+        rule_sets.len(),
+        karabiner_json_path
+    );
+    for rule_set in &rule_sets {
+        println!(
+            "    module_rule_set!(<priority>, {}), // {}",
+            rule_set.module_name, rule_set.description
+        );
+        if !rule_set.dropped_fields.is_empty() {
+            println!(
+                "        warning: {:?} of this rule are not carried over",
+                rule_set.dropped_fields
+            );
+        }
+    }
+    Ok(())
+}
+
+fn install(
+    config: &Config,
+    paths: &Paths,
+    profile_names: &[String],
+    force: bool,
+    keep_backups: usize,
+) -> anyhow::Result<()> {
+    let installer = Installer {
+        force,
+        keep_backups,
+        ..paths.installer(config, profile_names)
     };
+    let plan = install::plan(&installer)?;
+    for warning in created_profiles(&plan.reports) {
+        println!("warning: {}", warning);
+    }
+    if print_drifted(&plan.reports) > 0 && !force {
+        println!(
+            "Run `import --drifted` to keep the edits as rule sets, or `install --force` to overwrite them"
+        );
+    }
+    let installed = plan.write()?;
+
+    if let Some(backup) = installed.backup {
+        println!("Backed up karabiner.json to {:?}", backup.path);
+    }
+    for report in installed.reports {
+        if report.created {
+            println!("Created {}", report.target);
+        }
+        println!(
+            "Installed {} rule(s) into {}, replacing {} previously installed by karaconf",
+            report.rules, report.target, report.replaced
+        );
+        if !report.taken_over.is_empty() {
+            println!(
+                "Replaced {} rule(s) without the {:?} prefix, named like a rule set or installed before rule sets:",
+                report.taken_over.len(),
+                merge::OWNED_RULE_PREFIX
+            );
+            for description in &report.taken_over {
+                println!("    {}", description);
+            }
+        }
+        if !report.kept.is_empty() {
+            println!(
+                "Kept {} rule(s) not managed by karaconf:",
+                report.kept.len()
+            );
+            for description in &report.kept {
+                println!("    {}", description);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Warnings about the profiles the install creates, since a misspelled `--profile` would
+/// otherwise add a profile without notice.
+fn created_profiles(reports: &[ProfileReport]) -> Vec<String> {
+    reports
+        .iter()
+        .filter(|report| report.created)
+        .map(|report| {
+            format!(
+                "{} does not exist in karabiner.json and will be created; check the name if that is not intended",
+                report.target
+            )
+        })
+        .collect()
+}
+
+/// Prints how each rule edited outside karaconf differs from what karaconf would install, and
+/// returns how many there are.
+fn print_drifted(reports: &[ProfileReport]) -> usize {
+    let mut count = 0;
+    for report in reports
+        .iter()
+        .filter(|report| !report.drifted.is_empty() || !report.deleted.is_empty())
+    {
+        count += report.drifted.len() + report.deleted.len();
+        println!("Edited outside karaconf in {}:", report.target);
+        for diff in diff::diff_rules(&report.drifted, &report.owned) {
+            if !matches!(diff, diff::RuleDiff::Added { .. }) {
+                println!("    {}", diff.to_string().replace('\n', "\n    "));
+            }
+        }
+        // Rules whose manipulators are unchanged drifted in their other fields.
+        for rule in &report.drifted {
+            let Some(owned) = report
+                .owned
+                .iter()
+                .find(|r| r["description"] == rule["description"])
+            else {
+                continue;
+            };
+            if rule["manipulators"] != owned["manipulators"] {
+                continue;
+            }
+            println!(
+                "    ~ {} (rule fields)",
+                rule["description"].as_str().unwrap_or_default()
+            );
+            for (field, old, new) in diff::rule_field_changes(rule, owned)
+                .into_iter()
+                .filter(|(field, _, _)| !merge::RULE_STATE_FIELDS.contains(&field.as_str()))
+            {
+                if !old.is_null() {
+                    println!("        - {}: {}", field, old);
+                }
+                if !new.is_null() {
+                    println!("        + {}: {}", field, new);
+                }
+            }
+        }
+        for description in &report.deleted {
+            println!(
+                "    - {} (deleted; deselect its rule set to leave it out)",
+                description
+            );
+        }
+    }
+    count
+}
+
+fn restore(
+    paths: &Paths,
+    latest: bool,
+    timestamp: Option<&str>,
+    keep_backups: usize,
+) -> anyhow::Result<()> {
+    let karabiner_json_path = paths.karabiner_json()?;
+    let backups = backup::list_backups(&karabiner_json_path)?;
+    let backup = match (latest, timestamp) {
+        (true, _) => backups.last(),
+        (false, Some(timestamp)) => backups.iter().find(|b| b.timestamp == timestamp),
+        (false, None) => {
+            if backups.is_empty() {
+                println!("No backups of karabiner.json exist yet");
+            }
+            for backup in backups.iter().rev() {
+                println!("{}", backup.timestamp);
+            }
+            return Ok(());
+        }
+    }
+    .ok_or_else(|| {
+        anyhow::anyhow!("No such backup of karabiner.json; run `restore` to list them")
+    })?;
+    backup::restore(&karabiner_json_path, backup, keep_backups)?;
+    println!("Restored karabiner.json from {:?}", backup.path);
+    Ok(())
+}
+
+fn generate(config: &Config, file: Option<&std::path::Path>) -> anyhow::Result<()> {
+    let complex_modifications = config::generate(config);
+    let invalid = validate::validate_rules(&complex_modifications.rules);
+    if !invalid.is_empty() {
+        return Err(karaconf::Error::Invalid(invalid).into());
+    }
+    let custom_json = serde_json::to_vec_pretty(&complex_modifications)?;
+    match file {
+        Some(file) => Ok(atomic_write::write_all(&[(file, &custom_json)])?),
+        None => {
+            println!("{}", String::from_utf8(custom_json)?);
+            Ok(())
+        }
+    }
+}
+
+/// Returns whether everything is valid, printing each problem found. Warnings (e.g. conflicting
+/// bindings) are printed too but do not make the check fail.
+fn check(
+    config: &Config,
+    rule_sets: &[Box<dyn RuleSet>],
+    paths: &Paths,
+    profile_names: &[String],
+    fresh: bool,
+) -> anyhow::Result<bool> {
+    let mut problems = vec![];
+    let mut warnings = vec![];
+
+    let custom_json = String::from_utf8(serde_json::to_vec_pretty(&config::generate(config))?)?;
+    let generated: serde_json::Value = serde_json::from_str(&custom_json)?;
+    match serde_json::from_value::<karabiner_data::ComplexModifications>(generated.clone()) {
+        Ok(parsed) if serde_json::to_value(&parsed)? == generated => {}
+        Ok(_) => problems.push("generated custom.json does not round-trip".to_string()),
+        Err(e) => problems.push(format!("generated custom.json does not parse: {}", e)),
+    }
+
+    let manipulators = rule_sets
+        .iter()
+        .map(|rule_set| (rule_set.name().to_string(), rule_set.manipulators()))
+        .collect::<Vec<_>>();
+    for invalid in validate::validate(&manipulators) {
+        problems.push(invalid.to_string());
+    }
+
+    if fresh {
+        let committed = std::fs::read_to_string(&paths.output).unwrap_or_default();
+        // Compared as JSON, so a trailing newline (e.g. from `generate > custom.json` or an
+        // editor) or other formatting is not staleness.
+        if serde_json::from_str::<serde_json::Value>(&committed).ok() != Some(generated) {
+            let custom_json = if committed.ends_with('\n') {
+                custom_json + "\n"
+            } else {
+                custom_json
+            };
+            print!(
+                "{}",
+                diff::text_diff(&committed, &custom_json, &paths.output)
+            );
+            problems.push(format!(
+                "{} is stale; run `cargo run` (or `cargo run -- generate {}`) and commit it",
+                paths.output.display(),
+                paths.output.display()
+            ));
+        }
+    } else {
+        for conflict in analysis::conflicts(&manipulators) {
+            warnings.push(conflict.to_string());
+        }
+        for shadowed in analysis::shadowed(&manipulators) {
+            warnings.push(shadowed.to_string());
+        }
+        for issue in analysis::variable_issues(&manipulators) {
+            warnings.push(issue.to_string());
+        }
+
+        match install::plan(&paths.installer(config, profile_names)) {
+            Ok(plan) => {
+                warnings.extend(created_profiles(&plan.reports));
+                let drifted = print_drifted(&plan.reports);
+                if drifted > 0 {
+                    problems.push(format!(
+                        "{} rule(s) were edited outside karaconf since the last install",
+                        drifted
+                    ));
+                }
+            }
+            // Already reported above, by rule set name.
+            Err(karaconf::Error::Invalid(_)) => {}
+            Err(e) => problems.push(format!("{:#}", anyhow::Error::from(e))),
+        }
+    }
+
+    for warning in &warnings {
+        println!("warning: {}", warning);
+    }
+    if problems.is_empty() {
+        println!("ok");
+    }
+    for problem in &problems {
+        println!("error: {}", problem);
+    }
+    Ok(problems.is_empty())
+}
 
-    // https://karabiner-elements.pqrs.org/docs/json/location/
-    let config_dir = std::env::var("HOME")
-        .map(std::path::PathBuf::from)
-        .map_err(|e| anyhow::anyhow!("HOME environment variable is not set: {}", e))?
-        .join(".config/karabiner");
-    if !config_dir.is_dir() {
-        anyhow::bail!("{:?} must be created via Karabiner-Elements", config_dir);
-    }
-
-    // 1. write custom.json
-    let mut custom_json_file = std::fs::OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .read(true)
-        .open(CUSTOM_JSON_FILENAME)?;
-    serde_json::to_writer_pretty(&custom_json_file, &complex_modifications)?;
-
-    // 2. copy custom.json to karabiner assets (~/.config/karabiner/assets/complex_modifications/custom.json)
-    let mut karabiner_assets_file = std::fs::File::create(
-        config_dir
-            .join("assets/complex_modifications")
-            .join(CUSTOM_JSON_FILENAME),
-    )?;
-    custom_json_file.seek(std::io::SeekFrom::Start(0))?;
-    std::io::copy(&mut custom_json_file, &mut karabiner_assets_file)?;
-
-    // 3. update karabiner.json (~/.config/karabiner/karabiner.json)
-    let karabiner_json_path = config_dir.join("karabiner.json");
-    let mut karabiner_json: serde_json::Value =
-        serde_json::from_reader(&std::fs::File::open(&karabiner_json_path)?)?;
-    karabiner_json["profiles"]
-        .as_array_mut()
-        .ok_or_else(|| anyhow::anyhow!("Invalid karabiner.json format: profiles is not an array"))?
-        .get_mut(0)
-        .ok_or_else(|| anyhow::anyhow!("No profile exists in karabiner.json"))?["complex_modifications"]
-        .as_object_mut()
-        .ok_or_else(|| anyhow::anyhow!("complex_modifications is not an object"))?
-        .insert("rules".to_string(), serde_json::json!(&rules));
-    let karabiner_json_data = serde_json::to_vec_pretty(&karabiner_json)?;
-    std::fs::write(karabiner_json_path, karabiner_json_data)?;
+fn list(config: &Config, rule_sets: &[Box<dyn RuleSet>]) {
+    println!("Rule sets, in evaluation order:");
+    for rule_set in rule_sets {
+        println!(
+            "    {:<16} {:>4} {:>4} manipulators  {}",
+            rule_set.name(),
+            rule_set.priority(),
+            rule_set.manipulators().len(),
+            rule_set.description()
+        );
+    }
+    let disabled = rule_sets::registry()
+        .into_iter()
+        .filter(|r| !rule_sets.iter().any(|selected| selected.name() == r.name()))
+        .map(|r| r.name().to_string())
+        .collect::<Vec<_>>();
+    if !disabled.is_empty() {
+        println!("Disabled: {}", disabled.join(", "));
+    }
+    println!("Profiles:");
+    for profile in &config.profiles {
+        println!("    {}", profile.target);
+        for rule in &profile.rules {
+            println!(
+                "        {} ({} manipulators)",
+                rule.description,
+                rule.manipulators.len()
+            );
+        }
+    }
+}
+
+/// Prints how an install would change the files on disk, without writing anything.
+/// Returns whether any file would change.
+fn dry_run(
+    config: &Config,
+    paths: &Paths,
+    profile_names: &[String],
+    text: bool,
+) -> anyhow::Result<bool> {
+    let plan = install::plan(&paths.installer(config, profile_names))?;
+    for warning in created_profiles(&plan.reports) {
+        println!("warning: {}", warning);
+    }
+    if print_drifted(&plan.reports) > 0 {
+        println!("`install` will refuse to overwrite these edits without --force");
+    }
+    let karabiner_json = String::from_utf8(plan.karabiner_json_data()?)?;
+    let custom_json = String::from_utf8(plan.custom_json)?;
+    let files = plan
+        .output_path
+        .map(|output_path| (output_path, custom_json.clone()))
+        .into_iter()
+        .chain([
+            (plan.karabiner_assets_path, custom_json),
+            (plan.karabiner_json_path, karabiner_json),
+        ]);
+
+    let mut changed = false;
+    for (path, new) in files {
+        let old = std::fs::read_to_string(&path).unwrap_or_default();
+        if text {
+            let text_diff = diff::text_diff(&old, &new, &path);
+            changed |= !text_diff.is_empty();
+            print!("{}", text_diff);
+            continue;
+        }
+        let old: serde_json::Value = serde_json::from_str(&old).unwrap_or_default();
+        let new: serde_json::Value = serde_json::from_str(&new)?;
+        let diffs = if new.get("profiles").is_some() {
+            diff::diff_karabiner_json(&old, &new)
+        } else {
+            let rules =
+                |json: &serde_json::Value| json["rules"].as_array().cloned().unwrap_or_default();
+            vec![(String::new(), diff::diff_rules(&rules(&old), &rules(&new)))]
+                .into_iter()
+                .filter(|(_, diffs)| !diffs.is_empty())
+                .collect()
+        };
+        if diffs.is_empty() {
+            println!("{}: no changes", path.display());
+            continue;
+        }
+        changed = true;
+        println!("{}:", path.display());
+        for (profile, diffs) in diffs {
+            if !profile.is_empty() {
+                println!("  profile {:?}:", profile);
+            }
+            for diff in diffs {
+                println!("    {}", diff.to_string().replace('\n', "\n    "));
+            }
+        }
+    }
+    Ok(changed)
+}
+
+/// Replays pressing `key` with `mods` held through the selected rule sets and prints every
+/// manipulator that reacts to that key, why it does or does not fire, and what is sent.
+fn explain(
+    rule_sets: &[Box<dyn RuleSet>],
+    key: &str,
+    app: Option<&str>,
+    vars: &[String],
+    mods: &[String],
+) -> anyhow::Result<()> {
+    let key_code: KeyCode = serde_json::from_value(serde_json::Value::from(key.to_lowercase()))
+        .map_err(|_| anyhow::anyhow!("Unknown key code {:?}", key))?;
+    let manipulators = rule_sets
+        .iter()
+        .map(|rule_set| (rule_set.name().to_string(), rule_set.manipulators()))
+        .collect::<Vec<_>>();
+    let locations = analysis::locations(&manipulators);
+    let mut simulator = Simulator::new(manipulators.into_iter().flat_map(|(_, m)| m).collect());
+    if let Some(app) = app {
+        simulator = simulator.with_frontmost_application(app);
+    }
+    for var in vars {
+        let (name, value) = var
+            .split_once('=')
+            .ok_or_else(|| anyhow::anyhow!("--vars takes name=value, got {:?}", var))?;
+        // 1 and true are JSON values; anything else is a string.
+        let value = serde_json::from_str(value).unwrap_or(serde_json::Value::from(value));
+        simulator.set_variable(name, value);
+    }
+    // The modifiers are pressed like keys, so manipulators of modifier keys apply as on a Mac.
+    let mut time_ms = 0;
+    for m in mods {
+        let modifier: ModifierKey = serde_json::from_value(serde_json::Value::from(m.as_str()))
+            .map_err(|_| anyhow::anyhow!("Unknown modifier {:?}", m))?;
+        let modifier_key_code = modifier_key_code(&modifier)
+            .ok_or_else(|| anyhow::anyhow!("{:?} cannot be held down", m))?;
+        simulator.event(time_ms, KeyEvent::Down(modifier_key_code));
+        time_ms += 10;
+    }
+
+    let mut context = vec![];
+    if let Some(app) = app {
+        context.push(format!("in {}", app));
+    }
+    if !vars.is_empty() {
+        context.push(format!("with {}", vars.join(", ")));
+    }
+    let mut pressed = mods.to_vec();
+    pressed.push(key.to_lowercase());
+    context.insert(0, pressed.join(" + "));
+    println!("{}", context.join(" "));
+
+    let mut winner = None;
+    let mut checked = 0;
+    for (i, location) in locations.iter().enumerate() {
+        let verdict = match (simulator.mismatch(i, &key_code), &winner) {
+            (Some(Mismatch::FromEvent), _) => continue,
+            (Some(mismatch), _) => format!("skipped: {}", mismatch),
+            (None, None) => {
+                winner = Some(location);
+                "wins: the first manipulator that matches".to_string()
+            }
+            (None, Some(winner)) => format!("shadowed by {}", winner),
+        };
+        checked += 1;
+        if checked == 1 {
+            println!("Checked in this order:");
+        }
+        println!("    {}. {}: {}", checked, location, verdict);
+    }
+    if winner.is_none() {
+        println!("No manipulator matches, so the key is sent unchanged.");
+    }
+
+    println!("Sends:");
+    let outputs = simulator.run(&[
+        (time_ms, KeyEvent::Down(key_code.clone())),
+        (time_ms + 10, KeyEvent::Up(key_code)),
+    ]);
+    for output in &outputs {
+        let line = match output {
+            Output::KeyDown {
+                key_code,
+                modifiers,
+            } if modifiers.is_empty() => format!("key_down {}", json_name(key_code)),
+            Output::KeyDown {
+                key_code,
+                modifiers,
+            } => format!(
+                "key_down {} ({})",
+                json_name(key_code),
+                modifiers
+                    .iter()
+                    .map(json_name)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Output::KeyUp { key_code } => format!("key_up {}", json_name(key_code)),
+            Output::Event(event) => serde_json::to_string(event)?,
+        };
+        println!("    {}", line);
+    }
+    if outputs.is_empty() {
+        println!("    nothing");
+    }
+    for (name, value) in simulator.variables() {
+        println!("Variable {} is {} afterwards", name, value);
+    }
     Ok(())
 }
+
+/// The key to press to hold `modifier`; generic modifiers are the left key.
+fn modifier_key_code(modifier: &ModifierKey) -> Option<KeyCode> {
+    match modifier {
+        ModifierKey::Ctrl | ModifierKey::LeftControl => Some(KeyCode::LeftControl),
+        ModifierKey::Shift | ModifierKey::LeftShift => Some(KeyCode::LeftShift),
+        ModifierKey::Opt | ModifierKey::LeftOption | ModifierKey::LeftAlt => {
+            Some(KeyCode::LeftOption)
+        }
+        ModifierKey::Cmd | ModifierKey::LeftCommand | ModifierKey::LeftGui => {
+            Some(KeyCode::LeftCommand)
+        }
+        ModifierKey::RightControl => Some(KeyCode::RightControl),
+        ModifierKey::RightShift => Some(KeyCode::RightShift),
+        ModifierKey::RightOption | ModifierKey::RightAlt => Some(KeyCode::RightOption),
+        ModifierKey::RightCommand | ModifierKey::RightGui => Some(KeyCode::RightCommand),
+        ModifierKey::Fn => Some(KeyCode::Fn),
+        ModifierKey::Any | ModifierKey::CapsLock => None,
+    }
+}
+
+fn json_name<T: serde::Serialize>(value: &T) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(name)) => name,
+        Ok(value) => value.to_string(),
+        Err(_) => String::new(),
+    }
+}
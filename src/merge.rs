@@ -0,0 +1,101 @@
+use crate::{Error, karabiner_data::Rule};
+
+/// Descriptions of the rules karaconf generates start with this prefix, which is how
+/// `merge_rules` tells them apart from rules added through the Karabiner UI or the gallery.
+pub const OWNED_RULE_PREFIX: &str = "[karaconf] ";
+
+/// Descriptions of the single rule karaconf installed before rules were split per rule set,
+/// with and without the prefix. `merge_rules` always replaces them.
+pub const LEGACY_OWNED_DESCRIPTIONS: &[&str] = &["Personal rules", "[karaconf] Personal rules"];
+
+/// Rule fields the Karabiner UI changes on owned rules (e.g. turning a rule off), which installs
+/// carry over instead of resetting.
+pub const RULE_STATE_FIELDS: &[&str] = &["enabled"];
+
+pub fn owned_description(description: &str) -> String {
+    format!("{}{}", OWNED_RULE_PREFIX, description)
+}
+
+/// Whether a rule with `description` was installed by karaconf, now or before rule sets.
+pub fn is_owned_description(description: &str) -> bool {
+    description.starts_with(OWNED_RULE_PREFIX) || LEGACY_OWNED_DESCRIPTIONS.contains(&description)
+}
+
+#[derive(Debug)]
+pub struct MergedRules {
+    pub rules: Vec<serde_json::Value>,
+    /// Descriptions of the rules that are not owned by karaconf and were left in place.
+    pub kept: Vec<String>,
+    /// Number of previously installed karaconf rules that were replaced.
+    pub replaced: usize,
+    /// Descriptions of the rules without the prefix that were replaced too: those named like a
+    /// rule set and those in `LEGACY_OWNED_DESCRIPTIONS`.
+    pub taken_over: Vec<String>,
+}
+
+/// Replaces the karaconf-owned rules in `existing` with `owned`.
+///
+/// The new rules take the position of the first owned rule (or the front of the list when
+/// there is none), and every other rule keeps its relative order and is left untouched.
+/// Rules without the prefix whose description matches an owned rule once the prefix is
+/// stripped are treated as owned too, so rules installed before the prefix existed are replaced,
+/// as are the rules in `LEGACY_OWNED_DESCRIPTIONS`. They are reported in `taken_over`, since a
+/// rule from elsewhere that happens to have such a description is replaced too.
+/// `RULE_STATE_FIELDS` of a replaced rule are copied onto the new rule with the same description.
+pub fn merge_rules(existing: &[serde_json::Value], owned: &[Rule]) -> crate::Result<MergedRules> {
+    let is_owned = |rule: &serde_json::Value| {
+        rule["description"].as_str().is_some_and(|description| {
+            is_owned_description(description)
+                || owned
+                    .iter()
+                    .any(|r| r.description.strip_prefix(OWNED_RULE_PREFIX) == Some(description))
+        })
+    };
+
+    let insert_at = existing.iter().position(is_owned).unwrap_or(0);
+    let mut owned = owned
+        .iter()
+        .map(serde_json::to_value)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(Error::Json)?;
+    for rule in &mut owned {
+        let Some(previous) = existing
+            .iter()
+            .find(|previous| previous["description"] == rule["description"])
+        else {
+            continue;
+        };
+        for field in RULE_STATE_FIELDS {
+            if let (Some(rule), Some(value)) = (rule.as_object_mut(), previous.get(field)) {
+                rule.insert(field.to_string(), value.clone());
+            }
+        }
+    }
+    let mut rules = vec![];
+    let mut kept = vec![];
+    let mut replaced = 0;
+    let mut taken_over = vec![];
+    for (i, rule) in existing.iter().enumerate() {
+        if i == insert_at {
+            rules.extend(owned.iter().cloned());
+        }
+        let description = rule["description"].as_str().unwrap_or_default();
+        if !is_owned(rule) {
+            kept.push(description.to_string());
+            rules.push(rule.clone());
+        } else if description.starts_with(OWNED_RULE_PREFIX) {
+            replaced += 1;
+        } else {
+            taken_over.push(description.to_string());
+        }
+    }
+    if existing.is_empty() {
+        rules = owned;
+    }
+    Ok(MergedRules {
+        rules,
+        kept,
+        replaced,
+        taken_over,
+    })
+}
@@ -0,0 +1,87 @@
+use crate::{Error, karabiner_data::Rule};
+
+/// Which profile in karabiner.json a set of rules is installed into.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProfileTarget {
+    /// The first profile, whatever its name is.
+    First,
+    /// The profile with this name. It is created when karabiner.json does not have it yet.
+    Named(String),
+}
+
+impl std::fmt::Display for ProfileTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProfileTarget::First => write!(f, "the first profile"),
+            ProfileTarget::Named(name) => write!(f, "profile {:?}", name),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ProfileRules {
+    pub target: ProfileTarget,
+    pub rules: Vec<Rule>,
+}
+
+/// Returns the `complex_modifications` object of the targeted profile and whether the profile had to be created.
+/// `path` is where `karabiner_json` was read from, for errors.
+pub fn complex_modifications_mut<'a>(
+    karabiner_json: &'a mut serde_json::Value,
+    path: &std::path::Path,
+    target: &ProfileTarget,
+) -> crate::Result<(&'a mut serde_json::Map<String, serde_json::Value>, bool)> {
+    let unexpected = |what: String, expected: &str| Error::UnexpectedKarabinerJson {
+        path: path.to_path_buf(),
+        what,
+        expected: expected.to_string(),
+    };
+    let profiles = karabiner_json
+        .as_object_mut()
+        .ok_or_else(|| unexpected("the top level".to_string(), "an object"))?
+        .get_mut("profiles")
+        .and_then(serde_json::Value::as_array_mut)
+        .ok_or_else(|| unexpected("profiles".to_string(), "an array"))?;
+    let (index, created) = match target {
+        ProfileTarget::First => {
+            if profiles.is_empty() {
+                return Err(Error::NoProfile(path.to_path_buf()));
+            }
+            (0, false)
+        }
+        ProfileTarget::Named(name) => {
+            match profiles
+                .iter()
+                .position(|profile| profile["name"].as_str() == Some(name))
+            {
+                Some(index) => (index, false),
+                None => {
+                    profiles.push(serde_json::json!({
+                        "name": name,
+                        "complex_modifications": { "rules": [] },
+                    }));
+                    (profiles.len() - 1, true)
+                }
+            }
+        }
+    };
+    let complex_modifications = profiles[index]
+        .as_object_mut()
+        .ok_or_else(|| unexpected(target.to_string(), "an object"))?
+        .entry("complex_modifications")
+        .or_insert_with(|| serde_json::json!({}))
+        .as_object_mut()
+        .ok_or_else(|| unexpected(format!("complex_modifications of {}", target), "an object"))?;
+    Ok((complex_modifications, created))
+}
+
+/// Name of the targeted profile as it appears in karabiner.json.
+pub fn profile_name(karabiner_json: &serde_json::Value, target: &ProfileTarget) -> String {
+    match target {
+        ProfileTarget::First => karabiner_json["profiles"][0]["name"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+        ProfileTarget::Named(name) => name.clone(),
+    }
+}
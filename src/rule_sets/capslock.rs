@@ -1,5 +1,7 @@
 use crate::karabiner_data::{KeyCode as K, ModifierKey::*, *};
 
+pub const DESCRIPTION: &str = "Disable caps lock";
+
 pub fn manipulators() -> Vec<Manipulator> {
     vec![
         Manipulator::builder()
@@ -1,5 +1,7 @@
 use crate::karabiner_data::{KeyCode as K, ModifierKey::*, *};
 
+pub const DESCRIPTION: &str = "iTerm2: tmux prefix shortcuts and navigation";
+
 pub fn manipulators() -> Vec<Manipulator> {
     let vk1_conditions = vec![
         Condition::on_app(BundleIdentifier::ITerm2),
@@ -13,3 +13,216 @@ pub mod vk1;
 pub mod vk2;
 pub mod vk3;
 pub mod vscode;
+
+use crate::{
+    Error, analysis,
+    karabiner_data::{Manipulator, Rule},
+    merge,
+};
+
+/// A group of manipulators that is enabled, disabled and ordered as a unit.
+pub trait RuleSet {
+    /// Identifier used to enable, disable and order the rule set, e.g. on the command line.
+    fn name(&self) -> &str;
+    fn description(&self) -> &str;
+    /// Rule sets with a lower priority come first, and Karabiner uses the first manipulator
+    /// that matches an event.
+    fn priority(&self) -> i32;
+    fn manipulators(&self) -> Vec<Manipulator>;
+}
+
+/// A rule set backed by one of the modules in this directory.
+pub struct ModuleRuleSet {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub priority: i32,
+    pub manipulators: fn() -> Vec<Manipulator>,
+}
+
+impl RuleSet for ModuleRuleSet {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn description(&self) -> &str {
+        self.description
+    }
+
+    fn priority(&self) -> i32 {
+        self.priority
+    }
+
+    fn manipulators(&self) -> Vec<Manipulator> {
+        (self.manipulators)()
+    }
+}
+
+macro_rules! module_rule_set {
+    ($priority:expr, $module:ident) => {
+        Box::new(ModuleRuleSet {
+            name: stringify!($module),
+            description: $module::DESCRIPTION,
+            priority: $priority,
+            manipulators: $module::manipulators,
+        })
+    };
+}
+
+/// Every rule set in this directory. Modules generated by `import` are added here by hand.
+pub fn registry() -> Vec<Box<dyn RuleSet>> {
+    vec![
+        module_rule_set!(10, virtual_key),
+        module_rule_set!(20, iterm2),
+        module_rule_set!(30, vscode),
+        module_rule_set!(40, dynalist),
+        module_rule_set!(50, slack),
+        module_rule_set!(60, google_chrome),
+        module_rule_set!(70, notion),
+        module_rule_set!(80, chatgpt),
+        module_rule_set!(90, vk1),
+        module_rule_set!(100, vk2),
+        module_rule_set!(110, open_apps),
+        module_rule_set!(120, vk3),
+        module_rule_set!(130, semicolon),
+        module_rule_set!(140, singlequote),
+        module_rule_set!(150, capslock),
+    ]
+}
+
+/// Which rule sets to use, by name.
+#[derive(Debug, Default, Clone, PartialEq, Eq, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Selection {
+    /// When not empty, only these rule sets are used, in this order, instead of every rule set
+    /// ordered by priority.
+    pub enabled: Vec<String>,
+    pub disabled: Vec<String>,
+    /// Order by specificity (see `analysis::Specificity`) instead of relying on priorities alone.
+    pub sort_by_specificity: bool,
+}
+
+impl Selection {
+    /// Reads a selection such as `{"disabled": ["chatgpt"]}`; a missing file selects everything.
+    pub fn load(path: &std::path::Path) -> crate::Result<Self> {
+        if !path.exists() {
+            return Ok(Selection::default());
+        }
+        serde_json::from_slice(&std::fs::read(path).map_err(Error::io(path))?)
+            .map_err(Error::invalid_file(path))
+    }
+
+    /// `other` (e.g. from the command line) applied on top of `self`: its `enabled` list
+    /// replaces this one when given, and both `disabled` lists apply.
+    pub fn overridden_by(mut self, other: Selection) -> Self {
+        if !other.enabled.is_empty() {
+            self.enabled = other.enabled;
+        }
+        self.disabled.extend(other.disabled);
+        self.sort_by_specificity |= other.sort_by_specificity;
+        self
+    }
+}
+
+/// The rule sets of `registry` chosen by `selection`, in evaluation order.
+pub fn select(
+    mut registry: Vec<Box<dyn RuleSet>>,
+    selection: &Selection,
+) -> crate::Result<Vec<Box<dyn RuleSet>>> {
+    for name in selection.enabled.iter().chain(&selection.disabled) {
+        if !registry.iter().any(|rule_set| rule_set.name() == name) {
+            return Err(Error::UnknownRuleSet {
+                name: name.clone(),
+                available: registry
+                    .iter()
+                    .map(|rule_set| rule_set.name().to_string())
+                    .collect(),
+            });
+        }
+    }
+    registry.sort_by_key(|rule_set| rule_set.priority());
+    if !selection.enabled.is_empty() {
+        let mut enabled = vec![];
+        for name in &selection.enabled {
+            if let Some(i) = registry.iter().position(|r| r.name() == name) {
+                enabled.push(registry.remove(i));
+            }
+        }
+        registry = enabled;
+    }
+    registry.retain(|rule_set| {
+        !selection
+            .disabled
+            .iter()
+            .any(|name| rule_set.name() == name)
+    });
+    if selection.sort_by_specificity {
+        registry = sort_by_specificity(registry);
+    }
+    Ok(registry)
+}
+
+/// Sorts the manipulators of each rule set by specificity, then orders the rule sets by their most
+/// specific manipulator. Both sorts are stable, so the order chosen by priority is kept among
+/// equals. A rule set stays one Karabiner rule, so one that mixes general and app-specific
+/// manipulators can still shadow a later one; `check` reports that.
+fn sort_by_specificity(rule_sets: Vec<Box<dyn RuleSet>>) -> Vec<Box<dyn RuleSet>> {
+    let mut sorted = rule_sets
+        .into_iter()
+        .map(|rule_set| {
+            let mut manipulators = rule_set.manipulators();
+            analysis::sort_by_specificity(&mut manipulators);
+            let specificity = manipulators.iter().map(analysis::specificity).max();
+            (
+                specificity,
+                SortedRuleSet {
+                    rule_set,
+                    manipulators,
+                },
+            )
+        })
+        .collect::<Vec<_>>();
+    sorted.sort_by_key(|(specificity, _)| std::cmp::Reverse(*specificity));
+    sorted
+        .into_iter()
+        .map(|(_, rule_set)| Box::new(rule_set) as Box<dyn RuleSet>)
+        .collect()
+}
+
+/// A rule set with its manipulators in a different order.
+struct SortedRuleSet {
+    rule_set: Box<dyn RuleSet>,
+    manipulators: Vec<Manipulator>,
+}
+
+impl RuleSet for SortedRuleSet {
+    fn name(&self) -> &str {
+        self.rule_set.name()
+    }
+
+    fn description(&self) -> &str {
+        self.rule_set.description()
+    }
+
+    fn priority(&self) -> i32 {
+        self.rule_set.priority()
+    }
+
+    fn manipulators(&self) -> Vec<Manipulator> {
+        self.manipulators.clone()
+    }
+}
+
+/// One owned Karabiner rule per rule set, in the given order, so that each group can be turned
+/// off on its own in the Karabiner UI. Karabiner tries rules in order, so the evaluation order of
+/// the manipulators is the same as with a single rule holding all of them.
+pub fn rules(rule_sets: &[Box<dyn RuleSet>]) -> Vec<Rule> {
+    rule_sets
+        .iter()
+        .map(|rule_set| {
+            Rule::new(
+                merge::owned_description(rule_set.description()),
+                rule_set.manipulators(),
+            )
+        })
+        .collect()
+}
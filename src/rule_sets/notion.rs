@@ -1,5 +1,7 @@
 use crate::karabiner_data::{KeyCode as K, ModifierKey::*, *};
 
+pub const DESCRIPTION: &str = "Notion: page navigation";
+
 pub fn manipulators() -> Vec<Manipulator> {
     let vk2_conditions = vec![
         Condition::on_app(BundleIdentifier::Notion),
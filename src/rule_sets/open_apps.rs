@@ -1,5 +1,7 @@
 use crate::karabiner_data::{KeyCode as K, *};
 
+pub const DESCRIPTION: &str = "vk2: open applications and snippets";
+
 pub fn manipulators() -> Vec<Manipulator> {
     vec![
         // (K::A, "Ctrl+Shift+Tab"),
@@ -1,5 +1,7 @@
 use crate::karabiner_data::{KeyCode as K, ModifierKey::*, *};
 
+pub const DESCRIPTION: &str = "Semicolon as return; ctrl+semicolon types a semicolon";
+
 pub fn manipulators() -> Vec<Manipulator> {
     vec![
         Manipulator::builder()
@@ -1,5 +1,7 @@
 use crate::karabiner_data::{KeyCode as K, ModifierKey::*, *};
 
+pub const DESCRIPTION: &str = "Ctrl+single quote types a single quote";
+
 pub fn manipulators() -> Vec<Manipulator> {
     vec![
         Manipulator::builder()
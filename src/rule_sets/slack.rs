@@ -1,5 +1,7 @@
 use crate::karabiner_data::{KeyCode as K, ModifierKey::*, *};
 
+pub const DESCRIPTION: &str = "Slack: channel and message navigation";
+
 pub fn manipulators() -> Vec<Manipulator> {
     let vk4_conditions = vec![
         Condition::on_app(BundleIdentifier::Slack),
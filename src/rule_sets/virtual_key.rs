@@ -1,5 +1,7 @@
 use crate::karabiner_data::{KeyCode as K, ModifierKey::*, VirtualKey as VK, *};
 
+pub const DESCRIPTION: &str = "Virtual modifiers: kana/eisuu/right command/tab hold vk1-vk4";
+
 pub fn manipulators() -> Vec<Manipulator> {
     vec![
         (K::Lang1, VK::Vk1, Some(K::JapaneseKana)),
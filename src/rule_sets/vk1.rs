@@ -1,5 +1,7 @@
 use crate::karabiner_data::{KeyCode as K, ModifierKey::*, *};
 
+pub const DESCRIPTION: &str = "vk1 (kana): vim-style cursor movement and editing";
+
 pub fn manipulators() -> Vec<Manipulator> {
     vec![
         vec![
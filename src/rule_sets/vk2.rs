@@ -1,5 +1,7 @@
 use crate::karabiner_data::{KeyCode as K, ModifierKey::*, *};
 
+pub const DESCRIPTION: &str = "vk2 (eisuu): app switching, zoom, volume and brightness";
+
 pub fn manipulators() -> Vec<Manipulator> {
     vec![
         vec![
@@ -1,5 +1,7 @@
 use crate::karabiner_data::{KeyCode as K, ModifierKey::*, *};
 
+pub const DESCRIPTION: &str = "vk3 (right command): home row types numbers";
+
 pub fn manipulators() -> Vec<Manipulator> {
     vec![
         (K::A, K::Key1),
@@ -1,5 +1,7 @@
 use crate::karabiner_data::{KeyCode as K, ModifierKey::*, *};
 
+pub const DESCRIPTION: &str = "VSCode: editor navigation and commands";
+
 pub fn manipulators() -> Vec<Manipulator> {
     vec![
         vec![
@@ -0,0 +1,482 @@
+use crate::karabiner_data::{
+    Condition, ConditionType, FromEvent, KeyCode, Manipulator, ModifierKey, Rule, To,
+};
+
+/// `basic.to_if_alone_timeout_milliseconds` when a manipulator does not set it.
+const DEFAULT_TO_IF_ALONE_TIMEOUT_MS: u64 = 1000;
+
+/// A physical key press or release.
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeyEvent {
+    Down(KeyCode),
+    Up(KeyCode),
+}
+
+/// What Karabiner sends to macOS.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Output {
+    /// `modifiers` are the modifier flags of the event: the modifier keys held down in the output
+    /// (e.g. `LeftShift`) and those of the `to` event (e.g. `Cmd`).
+    KeyDown {
+        key_code: KeyCode,
+        modifiers: Vec<ModifierKey>,
+    },
+    KeyUp {
+        key_code: KeyCode,
+    },
+    /// Any other `to` event (shell command, mouse key, click, ...), as written in the manipulator.
+    Event(To),
+}
+
+/// Why a manipulator does not handle a key press.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Mismatch {
+    /// The manipulator reacts to another key or event.
+    FromEvent,
+    /// The modifiers held down do not fit `from.modifiers`.
+    Modifiers {
+        held: Vec<ModifierKey>,
+    },
+    Condition(Condition),
+}
+
+impl std::fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Mismatch::FromEvent => write!(f, "reacts to another key"),
+            Mismatch::Modifiers { held } => write!(
+                f,
+                "from.modifiers do not fit the held modifiers {}",
+                serde_json::to_string(held).unwrap_or_default()
+            ),
+            Mismatch::Condition(condition) => write!(
+                f,
+                "condition {} does not hold",
+                serde_json::to_string(condition).unwrap_or_default()
+            ),
+        }
+    }
+}
+
+/// A key held down in the input, and what it was turned into.
+#[derive(Debug)]
+struct Pressed {
+    key_code: KeyCode,
+    /// Index of the manipulator that handled the key press, if any.
+    manipulator: Option<usize>,
+    time_ms: u64,
+    /// Whether no other key was pressed since, so that `to_if_alone` may fire.
+    alone: bool,
+    /// The last `to` key, held down in the output until the key is released.
+    sent: Option<KeyCode>,
+    /// Modifier flags removed from the output because the manipulator required them.
+    lifted: Vec<ModifierKey>,
+}
+
+/// Replays key events through manipulators the way Karabiner-Elements does, so that keymaps can be
+/// tested without a Mac:
+///
+/// ```ignore
+/// let mut simulator = Simulator::new(manipulators);
+/// simulator.run(&[(0, KeyEvent::Down(K::Lang1)), (10, KeyEvent::Down(K::H))]);
+/// ```
+///
+/// Supported are key code `from` events with mandatory and optional modifiers, frontmost
+/// application and variable conditions, `to`, `to_after_key_up` and `to_if_alone` (with its
+/// timeout). `to_if_held_down`, `to_delayed_action`, simultaneous keys and other conditions (such as
+/// `device_if`, which never holds here) are not simulated. Bundle identifiers are matched as the
+/// plain or `^…$`-anchored patterns karaconf writes, not as full regular expressions.
+#[derive(Debug)]
+pub struct Simulator {
+    /// In evaluation order: the first manipulator that matches an event handles it.
+    manipulators: Vec<Manipulator>,
+    frontmost_application: Option<String>,
+    variables: std::collections::BTreeMap<String, serde_json::Value>,
+    pressed: Vec<Pressed>,
+    /// Keys held down in the output.
+    output_pressed: Vec<KeyCode>,
+}
+
+impl Simulator {
+    pub fn new(manipulators: Vec<Manipulator>) -> Self {
+        Simulator {
+            manipulators,
+            frontmost_application: None,
+            variables: std::collections::BTreeMap::new(),
+            pressed: vec![],
+            output_pressed: vec![],
+        }
+    }
+
+    /// Simulates the manipulators of `rules`, in order.
+    pub fn from_rules(rules: &[Rule]) -> Self {
+        Self::new(
+            rules
+                .iter()
+                .flat_map(|rule| rule.manipulators.clone())
+                .collect(),
+        )
+    }
+
+    /// Sets the bundle identifier of the frontmost application, e.g. `com.googlecode.iterm2`.
+    pub fn with_frontmost_application(mut self, bundle_identifier: impl Into<String>) -> Self {
+        self.frontmost_application = Some(bundle_identifier.into());
+        self
+    }
+
+    pub fn set_variable(&mut self, name: impl Into<String>, value: serde_json::Value) {
+        self.variables.insert(name.into(), value);
+    }
+
+    /// Variables set so far; variables never set count as 0.
+    pub fn variables(&self) -> &std::collections::BTreeMap<String, serde_json::Value> {
+        &self.variables
+    }
+
+    pub fn variable(&self, name: &str) -> serde_json::Value {
+        self.variables
+            .get(name)
+            .cloned()
+            .unwrap_or(serde_json::Value::from(0))
+    }
+
+    /// Feeds events with their time in milliseconds, returning everything sent in response.
+    pub fn run(&mut self, events: &[(u64, KeyEvent)]) -> Vec<Output> {
+        events
+            .iter()
+            .flat_map(|(time_ms, event)| self.event(*time_ms, event.clone()))
+            .collect()
+    }
+
+    /// Feeds one event that happens at `time_ms`, returning what is sent in response.
+    pub fn event(&mut self, time_ms: u64, event: KeyEvent) -> Vec<Output> {
+        match event {
+            KeyEvent::Down(key_code) => self.key_down(time_ms, key_code),
+            KeyEvent::Up(key_code) => self.key_up(time_ms, key_code),
+        }
+    }
+
+    /// Indices of the manipulators whose `from` and conditions match pressing `key_code` now, in
+    /// evaluation order. The first one handles the key press.
+    pub fn matching(&self, key_code: &KeyCode) -> Vec<usize> {
+        let held = self.modifiers();
+        self.manipulators
+            .iter()
+            .enumerate()
+            .filter(|(_, manipulator)| self.matches(manipulator, key_code, &held).is_ok())
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Why the manipulator at `index` does not handle pressing `key_code` now, or `None` when it
+    /// matches.
+    pub fn mismatch(&self, index: usize, key_code: &KeyCode) -> Option<Mismatch> {
+        self.matches(&self.manipulators[index], key_code, &self.modifiers())
+            .err()
+    }
+
+    pub fn manipulators(&self) -> &[Manipulator] {
+        &self.manipulators
+    }
+
+    fn key_down(&mut self, time_ms: u64, key_code: KeyCode) -> Vec<Output> {
+        for pressed in &mut self.pressed {
+            pressed.alone = false;
+        }
+        let held = self.modifiers();
+        let found = self
+            .manipulators
+            .iter()
+            .enumerate()
+            .find_map(|(i, manipulator)| {
+                self.matches(manipulator, &key_code, &held)
+                    .ok()
+                    .map(|lifted| (i, lifted))
+            });
+        let mut outputs = vec![];
+        let Some((index, lifted)) = found else {
+            outputs.push(Output::KeyDown {
+                key_code: key_code.clone(),
+                modifiers: held,
+            });
+            self.output_pressed.push(key_code.clone());
+            self.pressed.push(Pressed {
+                key_code: key_code.clone(),
+                manipulator: None,
+                time_ms,
+                alone: true,
+                sent: Some(key_code),
+                lifted: vec![],
+            });
+            return outputs;
+        };
+        let to = self.manipulators[index].to.clone();
+        let sent = self.send(&to, true, &lifted, &mut outputs);
+        self.pressed.push(Pressed {
+            key_code,
+            manipulator: Some(index),
+            time_ms,
+            alone: true,
+            sent,
+            lifted,
+        });
+        outputs
+    }
+
+    fn key_up(&mut self, time_ms: u64, key_code: KeyCode) -> Vec<Output> {
+        let mut outputs = vec![];
+        let Some(position) = self.pressed.iter().position(|p| p.key_code == key_code) else {
+            outputs.push(Output::KeyUp { key_code });
+            return outputs;
+        };
+        let pressed = self.pressed.remove(position);
+        if let Some(sent) = pressed.sent {
+            self.release(sent, &mut outputs);
+        }
+        let Some(index) = pressed.manipulator else {
+            return outputs;
+        };
+        let manipulator = self.manipulators[index].clone();
+        let timeout = manipulator
+            .extra
+            .get("parameters")
+            .and_then(|parameters| parameters["basic.to_if_alone_timeout_milliseconds"].as_u64())
+            .unwrap_or(DEFAULT_TO_IF_ALONE_TIMEOUT_MS);
+        if pressed.alone && time_ms.saturating_sub(pressed.time_ms) < timeout {
+            if let Some(to_if_alone) = &manipulator.to_if_alone {
+                self.send(to_if_alone, false, &pressed.lifted, &mut outputs);
+            }
+        }
+        if let Some(to_after_key_up) = &manipulator.to_after_key_up {
+            self.send(to_after_key_up, false, &pressed.lifted, &mut outputs);
+        }
+        outputs
+    }
+
+    /// Sends `events`; with `hold_last`, a key that is the last event stays down and is returned.
+    fn send(
+        &mut self,
+        events: &[To],
+        hold_last: bool,
+        lifted: &[ModifierKey],
+        outputs: &mut Vec<Output>,
+    ) -> Option<KeyCode> {
+        let mut held = None;
+        for (i, event) in events.iter().enumerate() {
+            match event {
+                To::Key {
+                    key_code,
+                    modifiers,
+                } => {
+                    // vk_none sends nothing; it only makes a manipulator swallow its key.
+                    if *key_code == KeyCode::VkNone {
+                        continue;
+                    }
+                    let mut flags = self
+                        .modifiers()
+                        .into_iter()
+                        .filter(|m| !lifted.contains(m))
+                        .collect::<Vec<_>>();
+                    for m in modifiers.iter().flatten() {
+                        if !flags.contains(m) {
+                            flags.push(m.clone());
+                        }
+                    }
+                    outputs.push(Output::KeyDown {
+                        key_code: key_code.clone(),
+                        modifiers: flags,
+                    });
+                    self.output_pressed.push(key_code.clone());
+                    if hold_last && i == events.len() - 1 {
+                        held = Some(key_code.clone());
+                    } else {
+                        self.release(key_code.clone(), outputs);
+                    }
+                }
+                To::Variable { set_variable } => {
+                    self.variables.insert(
+                        set_variable.name.as_str().to_string(),
+                        serde_json::Value::from(set_variable.value),
+                    );
+                }
+                To::Other(value) if value.get("set_variable").is_some() => {
+                    let set_variable = &value["set_variable"];
+                    if let Some(name) = set_variable["name"].as_str() {
+                        self.variables
+                            .insert(name.to_string(), set_variable["value"].clone());
+                    }
+                }
+                _ => outputs.push(Output::Event(event.clone())),
+            }
+        }
+        held
+    }
+
+    fn release(&mut self, key_code: KeyCode, outputs: &mut Vec<Output>) {
+        if let Some(i) = self.output_pressed.iter().position(|k| *k == key_code) {
+            self.output_pressed.remove(i);
+        }
+        outputs.push(Output::KeyUp { key_code });
+    }
+
+    /// Modifier keys held down in the output.
+    fn modifiers(&self) -> Vec<ModifierKey> {
+        let mut modifiers = vec![];
+        for m in self.output_pressed.iter().filter_map(modifier) {
+            if !modifiers.contains(&m) {
+                modifiers.push(m);
+            }
+        }
+        modifiers
+    }
+
+    /// When `manipulator` handles pressing `key_code` with `held` modifiers, returns the held
+    /// modifiers its mandatory modifiers consume; otherwise returns why it does not.
+    fn matches(
+        &self,
+        manipulator: &Manipulator,
+        key_code: &KeyCode,
+        held: &[ModifierKey],
+    ) -> Result<Vec<ModifierKey>, Mismatch> {
+        if manipulator.from.event.as_ref() != Some(&FromEvent::KeyCode(key_code.clone())) {
+            return Err(Mismatch::FromEvent);
+        }
+        if let Some(condition) = manipulator
+            .conditions
+            .iter()
+            .flatten()
+            .find(|condition| !self.holds(condition))
+        {
+            return Err(Mismatch::Condition(condition.clone()));
+        }
+        let (mandatory, optional) = match &manipulator.from.modifiers {
+            Some(modifiers) => (modifiers.mandatory(), modifiers.optional()),
+            None => (&[][..], &[][..]),
+        };
+        let modifiers_mismatch = || Mismatch::Modifiers {
+            held: held.to_vec(),
+        };
+        let mut lifted = vec![];
+        for m in mandatory {
+            let h = held
+                .iter()
+                .find(|h| satisfies(m, h))
+                .ok_or_else(modifiers_mismatch)?;
+            lifted.push(h.clone());
+        }
+        let extra_allowed = held.iter().all(|h| {
+            lifted.contains(h)
+                || optional
+                    .iter()
+                    .any(|o| *o == ModifierKey::Any || satisfies(o, h))
+        });
+        if !extra_allowed {
+            return Err(modifiers_mismatch());
+        }
+        Ok(lifted)
+    }
+
+    fn holds(&self, condition: &Condition) -> bool {
+        match condition {
+            Condition::OnApplication {
+                r#type,
+                bundle_identifiers,
+            } => {
+                let frontmost = bundle_identifiers
+                    .iter()
+                    .any(|b| self.frontmost_is(b.as_str()));
+                match r#type {
+                    ConditionType::FrontmostApplicationUnless => !frontmost,
+                    _ => frontmost,
+                }
+            }
+            Condition::WithVirtualKey {
+                r#type,
+                name,
+                value,
+            } => {
+                let set = self.variable(name.as_str()) == *value;
+                match r#type {
+                    ConditionType::VariableUnless => !set,
+                    _ => set,
+                }
+            }
+            Condition::Other(value) => {
+                let set = || {
+                    value["name"]
+                        .as_str()
+                        .is_some_and(|name| self.variable(name) == value["value"])
+                };
+                let frontmost = || {
+                    value["bundle_identifiers"]
+                        .as_array()
+                        .into_iter()
+                        .flatten()
+                        .filter_map(|b| b.as_str())
+                        .any(|b| self.frontmost_is(b))
+                };
+                match value["type"].as_str() {
+                    Some("variable_if") => set(),
+                    Some("variable_unless") => !set(),
+                    Some("frontmost_application_if") => frontmost(),
+                    Some("frontmost_application_unless") => !frontmost(),
+                    _ => false,
+                }
+            }
+        }
+    }
+
+    fn frontmost_is(&self, pattern: &str) -> bool {
+        let Some(frontmost) = &self.frontmost_application else {
+            return false;
+        };
+        let (start, pattern) = match pattern.strip_prefix('^') {
+            Some(pattern) => (true, pattern),
+            None => (false, pattern),
+        };
+        let (end, pattern) = match pattern.strip_suffix('$') {
+            Some(pattern) => (true, pattern),
+            None => (false, pattern),
+        };
+        let pattern = pattern.replace("\\.", ".");
+        match (start, end) {
+            (true, true) => *frontmost == pattern,
+            (true, false) => frontmost.starts_with(&pattern),
+            (false, true) => frontmost.ends_with(&pattern),
+            (false, false) => frontmost.contains(&pattern),
+        }
+    }
+}
+
+/// The modifier flag a key sets while held down.
+fn modifier(key_code: &KeyCode) -> Option<ModifierKey> {
+    match key_code {
+        KeyCode::LeftControl => Some(ModifierKey::LeftControl),
+        KeyCode::LeftShift => Some(ModifierKey::LeftShift),
+        KeyCode::LeftAlt | KeyCode::LeftOption => Some(ModifierKey::LeftOption),
+        KeyCode::LeftGui | KeyCode::LeftCommand => Some(ModifierKey::LeftCommand),
+        KeyCode::RightControl => Some(ModifierKey::RightControl),
+        KeyCode::RightShift => Some(ModifierKey::RightShift),
+        KeyCode::RightAlt | KeyCode::RightOption => Some(ModifierKey::RightOption),
+        KeyCode::RightGui | KeyCode::RightCommand => Some(ModifierKey::RightCommand),
+        KeyCode::Fn => Some(ModifierKey::Fn),
+        _ => None,
+    }
+}
+
+/// Whether holding `held` satisfies `modifier` in `from.modifiers`; `shift` accepts either shift key.
+fn satisfies(modifier: &ModifierKey, held: &ModifierKey) -> bool {
+    use ModifierKey::*;
+    modifier == held
+        || matches!(
+            (modifier, held),
+            (Ctrl, LeftControl | RightControl)
+                | (Shift, LeftShift | RightShift)
+                | (Opt, LeftOption | RightOption)
+                | (Cmd, LeftCommand | RightCommand)
+                | (LeftAlt, LeftOption)
+                | (LeftGui, LeftCommand)
+                | (RightAlt, RightOption)
+                | (RightGui, RightCommand)
+        )
+}
@@ -0,0 +1,197 @@
+use crate::{
+    analysis::{self, Location},
+    karabiner_data::{FROM_EVENT_KEYS, Manipulator, Rule},
+};
+
+/// Key codes that act as modifiers when held.
+const MODIFIER_KEY_CODES: &[&str] = &[
+    "left_control",
+    "left_shift",
+    "left_option",
+    "left_command",
+    "left_gui",
+    "right_control",
+    "right_shift",
+    "right_option",
+    "right_command",
+    "right_gui",
+    "fn",
+];
+
+/// Events a manipulator sends; one of them must be non-empty for the manipulator to do anything.
+const TO_FIELDS: &[&str] = &[
+    "to",
+    "to_if_alone",
+    "to_after_key_up",
+    "to_if_held_down",
+    "to_delayed_action",
+];
+
+/// `mouse_key` movement and wheel values must fit a signed 16-bit integer.
+const MOUSE_KEY_RANGE: std::ops::RangeInclusive<i64> = i16::MIN as i64..=i16::MAX as i64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Problem {
+    /// `from` has no event (`"from": {}`).
+    MissingFrom,
+    /// Every `to*` field is empty.
+    NoEffect,
+    /// `to_if_alone` on a modifier key without `to`: the key no longer works as a modifier.
+    ToIfAloneOnModifier { key_code: String },
+    /// A value Karabiner does not accept, e.g. a `mouse_key` speed out of range.
+    OutOfRange {
+        field: String,
+        value: serde_json::Value,
+        expected: String,
+    },
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Problem::MissingFrom => write!(f, "from has no event"),
+            Problem::NoEffect => write!(f, "does nothing: {} are all empty", TO_FIELDS.join(", ")),
+            Problem::ToIfAloneOnModifier { key_code } => write!(
+                f,
+                "to_if_alone on modifier {:?} without to; it no longer works as a modifier when held",
+                key_code
+            ),
+            Problem::OutOfRange {
+                field,
+                value,
+                expected,
+            } => write!(f, "{} is {}, expected {}", field, value, expected),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Invalid {
+    pub location: Location,
+    pub problem: Problem,
+}
+
+impl std::fmt::Display for Invalid {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid manipulator {}: {}", self.location, self.problem)
+    }
+}
+
+/// Checks the JSON each manipulator serializes to against what Karabiner accepts. Karabiner
+/// ignores invalid manipulators (or the whole file) without saying why, so this runs before
+/// anything is written.
+pub fn validate(rule_sets: &[(String, Vec<Manipulator>)]) -> Vec<Invalid> {
+    let manipulators = rule_sets.iter().flat_map(|(_, manipulators)| manipulators);
+    let mut invalid = vec![];
+    for (location, manipulator) in analysis::locations(rule_sets).into_iter().zip(manipulators) {
+        // Serializing a manipulator does not fail; `Null` would be reported as missing `from`.
+        let json = serde_json::to_value(manipulator).unwrap_or_default();
+        for problem in problems(&json) {
+            invalid.push(Invalid {
+                location: location.clone(),
+                problem,
+            });
+        }
+    }
+    invalid
+}
+
+/// `validate` for rules, named by their description.
+pub fn validate_rules(rules: &[Rule]) -> Vec<Invalid> {
+    let rule_sets = rules
+        .iter()
+        .map(|rule| (rule.description.clone(), rule.manipulators.clone()))
+        .collect::<Vec<_>>();
+    validate(&rule_sets)
+}
+
+fn problems(manipulator: &serde_json::Value) -> Vec<Problem> {
+    let mut problems = vec![];
+    let from = &manipulator["from"];
+    let has_event = FROM_EVENT_KEYS
+        .iter()
+        .any(|event| from.get(event).is_some());
+    if !has_event {
+        problems.push(Problem::MissingFrom);
+    }
+
+    let non_empty = |field: &str| {
+        manipulator
+            .get(field)
+            .is_some_and(|events| events.as_array().is_none_or(|events| !events.is_empty()))
+    };
+    if !TO_FIELDS.iter().any(|field| non_empty(field)) {
+        problems.push(Problem::NoEffect);
+    }
+    if let Some(key_code) = from["key_code"].as_str() {
+        if MODIFIER_KEY_CODES.contains(&key_code) && non_empty("to_if_alone") && !non_empty("to") {
+            problems.push(Problem::ToIfAloneOnModifier {
+                key_code: key_code.to_string(),
+            });
+        }
+    }
+
+    for field in TO_FIELDS {
+        let events = match &manipulator[field] {
+            serde_json::Value::Array(events) => events.iter().collect::<Vec<_>>(),
+            serde_json::Value::Null => vec![],
+            event => vec![event],
+        };
+        for (i, event) in events.into_iter().enumerate() {
+            let field = format!("{}[{}]", field, i);
+            if let Some(mouse_key) = event.get("mouse_key") {
+                for axis in ["x", "y", "vertical_wheel", "horizontal_wheel"] {
+                    let value = &mouse_key[axis];
+                    if !value.is_null()
+                        && !value.as_i64().is_some_and(|v| MOUSE_KEY_RANGE.contains(&v))
+                    {
+                        problems.push(Problem::OutOfRange {
+                            field: format!("{}.mouse_key.{}", field, axis),
+                            value: value.clone(),
+                            expected: format!(
+                                "an integer from {} to {}",
+                                MOUSE_KEY_RANGE.start(),
+                                MOUSE_KEY_RANGE.end()
+                            ),
+                        });
+                    }
+                }
+                let speed_multiplier = &mouse_key["speed_multiplier"];
+                if !speed_multiplier.is_null()
+                    && !speed_multiplier.as_f64().is_some_and(|v| v > 0.0)
+                {
+                    problems.push(Problem::OutOfRange {
+                        field: format!("{}.mouse_key.speed_multiplier", field),
+                        value: speed_multiplier.clone(),
+                        expected: "a positive number".to_string(),
+                    });
+                }
+            }
+            if let Some(set_variable) = event.get("set_variable") {
+                let value = &set_variable["value"];
+                // `"type": "unset"` removes the variable and takes no value.
+                let unset = set_variable["type"] == "unset";
+                if !(unset || value.is_i64() || value.is_boolean() || value.is_string()) {
+                    problems.push(Problem::OutOfRange {
+                        field: format!("{}.set_variable.value", field),
+                        value: value.clone(),
+                        expected: "an integer, a boolean or a string".to_string(),
+                    });
+                }
+            }
+        }
+    }
+
+    if let Some(parameters) = manipulator["parameters"].as_object() {
+        for (name, value) in parameters {
+            if name.ends_with("_milliseconds") && value.as_u64().is_none() {
+                problems.push(Problem::OutOfRange {
+                    field: format!("parameters.{}", name),
+                    value: value.clone(),
+                    expected: "a non-negative integer".to_string(),
+                });
+            }
+        }
+    }
+    problems
+}
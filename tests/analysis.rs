@@ -0,0 +1,251 @@
+use karaconf::{
+    analysis::{self, ConflictKind, Location, VariableProblem},
+    karabiner_data::{KeyCode as K, ModifierKey::*, VirtualKey as VK, *},
+};
+
+fn rule_set(name: &str, manipulators: Vec<Manipulator>) -> (String, Vec<Manipulator>) {
+    (name.to_string(), manipulators)
+}
+
+fn at(location: &Location) -> (&str, usize) {
+    (&location.rule_set, location.index)
+}
+
+fn vscode_vk4_j() -> Manipulator {
+    Manipulator::builder()
+        .condition(Condition::on_app(BundleIdentifier::VSCode))
+        .condition(Condition::with_vk4())
+        .from_key(K::J)
+        .to_key(K::J, Some(vec![Ctrl, Shift, Opt, Cmd]))
+        .build()
+}
+
+fn vk1_w() -> Manipulator {
+    Manipulator::builder()
+        .condition(Condition::with_vk1())
+        .from_key(K::W)
+        .to_key(K::W, Some(vec![Opt]))
+        .build()
+}
+
+fn iterm2_vk1_w() -> Manipulator {
+    Manipulator::builder()
+        .condition(Condition::on_app(BundleIdentifier::ITerm2))
+        .condition(Condition::with_vk1())
+        .from_key(K::W)
+        .to_key(K::Escape, None)
+        .build()
+}
+
+fn any_a() -> Manipulator {
+    Manipulator::builder()
+        .from_key_with_modifiers(K::A, FromModifier::Optional(vec![Any]))
+        .to_key(K::B, None)
+        .build()
+}
+
+fn shift_a() -> Manipulator {
+    Manipulator::builder()
+        .from_key_with_modifiers(K::A, FromModifier::Mandatory(vec![Shift]))
+        .to_key(K::C, None)
+        .build()
+}
+
+#[test]
+fn same_binding_in_two_rule_sets_is_a_duplicate() {
+    let conflicts = analysis::conflicts(&[
+        rule_set("vscode", vec![vscode_vk4_j()]),
+        rule_set("vscode_copilot", vec![vscode_vk4_j()]),
+    ]);
+    assert_eq!(conflicts.len(), 1);
+    assert_eq!(conflicts[0].kind, ConflictKind::Duplicate);
+    assert_eq!(at(&conflicts[0].first), ("vscode", 0));
+    assert_eq!(at(&conflicts[0].second), ("vscode_copilot", 0));
+}
+
+#[test]
+fn bindings_that_match_some_of_the_same_presses_overlap() {
+    let with = |mandatory| {
+        Manipulator::builder()
+            .from_key_with_modifiers(
+                K::A,
+                FromModifier::MandatoryAndOptional {
+                    mandatory: vec![mandatory],
+                    optional: vec![Any],
+                },
+            )
+            .to_key(K::B, None)
+            .build()
+    };
+    let conflicts = analysis::conflicts(&[rule_set("a", vec![with(Shift), with(Cmd)])]);
+    assert_eq!(conflicts.len(), 1);
+    assert_eq!(conflicts[0].kind, ConflictKind::Overlap);
+    assert_eq!(at(&conflicts[0].first), ("a", 0));
+    assert_eq!(at(&conflicts[0].second), ("a", 1));
+}
+
+#[test]
+fn overrides_and_other_layers_are_not_conflicts() {
+    let vk2_w = Manipulator::builder()
+        .condition(Condition::with_vk2())
+        .from_key(K::W)
+        .to_key(K::W, None)
+        .build();
+    let rule_sets = [
+        rule_set("iterm2", vec![iterm2_vk1_w()]),
+        rule_set("vk1", vec![vk1_w()]),
+        rule_set("vk2", vec![vk2_w]),
+    ];
+    assert_eq!(analysis::conflicts(&rule_sets), vec![]);
+    assert_eq!(analysis::shadowed(&rule_sets), vec![]);
+}
+
+#[test]
+fn global_binding_before_an_app_specific_one_shadows_it() {
+    let shadowed = analysis::shadowed(&[
+        rule_set("vk1", vec![vk1_w()]),
+        rule_set("iterm2", vec![iterm2_vk1_w()]),
+    ]);
+    assert_eq!(shadowed.len(), 1);
+    assert_eq!(at(&shadowed[0].shadowed), ("iterm2", 0));
+    assert_eq!(at(&shadowed[0].by), ("vk1", 0));
+}
+
+#[test]
+fn optional_any_before_mandatory_shift_shadows_it() {
+    let shadowed = analysis::shadowed(&[rule_set("a", vec![any_a(), shift_a()])]);
+    assert_eq!(shadowed.len(), 1);
+    assert_eq!(at(&shadowed[0].shadowed), ("a", 1));
+    assert_eq!(at(&shadowed[0].by), ("a", 0));
+
+    assert_eq!(
+        analysis::shadowed(&[rule_set("a", vec![shift_a(), any_a()])]),
+        vec![]
+    );
+}
+
+#[test]
+fn sorting_by_specificity_puts_special_cases_first() {
+    let mut manipulators = vec![any_a(), shift_a(), vk1_w(), iterm2_vk1_w()];
+    analysis::sort_by_specificity(&mut manipulators);
+    assert_eq!(
+        manipulators,
+        vec![iterm2_vk1_w(), vk1_w(), shift_a(), any_a()]
+    );
+    assert_eq!(analysis::shadowed(&[rule_set("a", manipulators)]), vec![]);
+}
+
+#[test]
+fn sorting_by_specificity_keeps_the_order_of_equals() {
+    let b = Manipulator::builder()
+        .from_key_with_modifiers(K::B, FromModifier::Optional(vec![Any]))
+        .to_key(K::C, None)
+        .build();
+    let mut manipulators = vec![b.clone(), any_a()];
+    analysis::sort_by_specificity(&mut manipulators);
+    assert_eq!(manipulators, vec![b, any_a()]);
+}
+
+#[test]
+fn variables_set_released_and_read_have_no_issues() {
+    let hold = Manipulator::builder()
+        .from_key_with_modifiers(K::Lang1, FromModifier::Optional(vec![Any]))
+        .to_variable(SetVariable {
+            name: VK::Vk1,
+            value: 1,
+        })
+        .to_after_key_up(SetVariable {
+            name: VK::Vk1,
+            value: 0,
+        })
+        .build();
+    assert_eq!(
+        analysis::variable_issues(&[
+            rule_set("virtual_key", vec![hold]),
+            rule_set("vk1", vec![vk1_w()])
+        ]),
+        vec![]
+    );
+}
+
+#[test]
+fn a_toggle_is_released_by_another_manipulator() {
+    let toggle = |from, value| {
+        Manipulator::builder()
+            .from_key(from)
+            .to_variable(SetVariable {
+                name: VK::Vk3,
+                value,
+            })
+            .build()
+    };
+    let read = Manipulator::builder()
+        .condition(Condition::with_vk3())
+        .from_key(K::H)
+        .to_key(K::LeftArrow, None)
+        .build();
+    assert_eq!(
+        analysis::variable_issues(&[rule_set(
+            "toggle",
+            vec![toggle(K::F1, 1), toggle(K::F2, 0), read]
+        )]),
+        vec![]
+    );
+}
+
+#[test]
+fn variable_issues_are_reported_once_per_variable() {
+    let unset = Manipulator::builder()
+        .condition(Condition::with_virtual_key(VK::Other("hyper".to_string())))
+        .from_key(K::H)
+        .to_key(K::LeftArrow, None)
+        .build();
+    let stuck = Manipulator::builder()
+        .from_key(K::Lang2)
+        .to_variable(SetVariable {
+            name: VK::Vk2,
+            value: 1,
+        })
+        .build();
+    let read_vk2 = |from| {
+        Manipulator::builder()
+            .condition(Condition::with_vk2())
+            .from_key(from)
+            .to_key(K::Escape, None)
+            .build()
+    };
+    let unread = Manipulator::builder()
+        .from_key(K::RightGui)
+        .to_variable(SetVariable {
+            name: VK::Vk3,
+            value: 1,
+        })
+        .to_after_key_up(SetVariable {
+            name: VK::Vk3,
+            value: 0,
+        })
+        .build();
+
+    let issues = analysis::variable_issues(&[rule_set(
+        "a",
+        vec![unset, stuck, read_vk2(K::J), read_vk2(K::K), unread],
+    )]);
+    let issues = issues
+        .iter()
+        .map(|issue| {
+            (
+                issue.problem.clone(),
+                issue.variable.as_str(),
+                at(&issue.location),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        issues,
+        vec![
+            (VariableProblem::NeverSet, "hyper", ("a", 0)),
+            (VariableProblem::NeverReleased, "vk2", ("a", 1)),
+            (VariableProblem::NeverRead, "vk3", ("a", 4)),
+        ]
+    );
+}
@@ -0,0 +1,73 @@
+use karaconf::atomic_write;
+
+/// An empty directory holding `files`.
+fn dir_with(name: &str, files: &[(&str, &str)]) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("karaconf-atomic-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    for (name, contents) in files {
+        std::fs::write(dir.join(name), contents).unwrap();
+    }
+    dir
+}
+
+fn file_names(dir: &std::path::Path) -> Vec<String> {
+    let mut names = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+        .collect::<Vec<_>>();
+    names.sort();
+    names
+}
+
+#[test]
+fn a_failed_write_touches_no_target() {
+    let dir = dir_with("write", &[("a.json", "old")]);
+
+    let result = atomic_write::write_all(&[
+        (&dir.join("a.json"), b"new"),
+        (&dir.join("missing/b.json"), b"new"),
+    ]);
+
+    assert!(result.is_err());
+    assert_eq!(std::fs::read_to_string(dir.join("a.json")).unwrap(), "old");
+    assert_eq!(file_names(&dir), ["a.json"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn a_failed_rename_puts_back_the_files_already_replaced() {
+    let dir = dir_with("rename", &[("a.json", "old")]);
+    // A non-empty directory cannot be replaced by a file, so the last rename fails.
+    std::fs::create_dir_all(dir.join("c.json/inside")).unwrap();
+
+    let result = atomic_write::write_all(&[
+        (&dir.join("a.json"), b"new"),
+        (&dir.join("b.json"), b"new"),
+        (&dir.join("c.json"), b"new"),
+    ]);
+
+    assert!(result.is_err());
+    assert_eq!(std::fs::read_to_string(dir.join("a.json")).unwrap(), "old");
+    assert_eq!(file_names(&dir), ["a.json", "c.json"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn replaced_files_keep_their_permissions() {
+    use std::os::unix::fs::PermissionsExt as _;
+    let dir = dir_with("permissions", &[("karabiner.json", "{}")]);
+    let path = dir.join("karabiner.json");
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+
+    atomic_write::write_all(&[(&path, b"{\"profiles\": []}")]).unwrap();
+
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        "{\"profiles\": []}"
+    );
+    let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,111 @@
+use crate::karabiner_data::{KeyCode as K, ModifierKey::*, VirtualKey as VK, *};
+
+pub const DESCRIPTION: &str = "Imported: every builder call";
+
+pub fn manipulators() -> Vec<Manipulator> {
+    vec![
+        Manipulator::builder()
+            .from_key(K::A)
+            .to_key(K::B, None)
+            .build(),
+        Manipulator::builder()
+            .condition(Condition::on_app(BundleIdentifier::Other(
+                "^com\\.googlecode\\.iterm2$".to_string(),
+            )))
+            .condition(Condition::with_vk1())
+            .from_key_with_modifiers(K::H, FromModifier::Optional(vec![Any]))
+            .to_key(K::LeftArrow, None)
+            .build(),
+        Manipulator::builder()
+            .condition(Condition::OnApplication {
+                r#type: ConditionType::FrontmostApplicationUnless,
+                bundle_identifiers: vec![
+                    BundleIdentifier::Other("^com\\.googlecode\\.iterm2$".to_string()),
+                    BundleIdentifier::Other("^com\\.example\\.editor$".to_string()),
+                ],
+            })
+            .condition(Condition::WithVirtualKey {
+                r#type: ConditionType::VariableUnless,
+                name: VK::Vk2,
+                value: 1,
+            })
+            .condition(Condition::with_virtual_key(VK::Other(
+                "hyper_mode".to_string(),
+            )))
+            .from_key_with_modifiers(
+                K::W,
+                FromModifier::MandatoryAndOptional {
+                    mandatory: vec![Cmd],
+                    optional: vec![Shift, CapsLock],
+                },
+            )
+            .to_key(
+                K::W,
+                Some(vec![
+                    LeftCommand,
+                    LeftControl,
+                    LeftOption,
+                    LeftShift,
+                    RightShift,
+                ]),
+            )
+            .build(),
+        Manipulator::builder()
+            .from_key_with_modifiers(K::Lang1, FromModifier::Optional(vec![Any]))
+            .to_variable(SetVariable {
+                name: VK::Vk1,
+                value: 1,
+            })
+            .to_after_key_up(SetVariable {
+                name: VK::Vk1,
+                value: 0,
+            })
+            .to_if_alone(K::JapaneseKana)
+            .build(),
+        Manipulator::builder()
+            .condition(Condition::with_vk1())
+            .from_key_with_modifiers(K::J, FromModifier::Mandatory(vec![Shift]))
+            .to_mouse(MouseKey {
+                x: None,
+                y: Some(1536),
+                vertical_wheel: None,
+            })
+            .build(),
+        Manipulator::builder()
+            .conditions(vec![])
+            .from_key(K::M)
+            .to_click(PointingButton::Button1)
+            .to_command("open -a 'Visual Studio Code.app'")
+            .build(),
+        Manipulator::builder()
+            .condition(Condition::Other(serde_json::json!({
+                "type": "device_if",
+                "identifiers": [
+                    {
+                        "vendor_id": 1452
+                    }
+                ]
+            })))
+            .from_key(K::CapsLock)
+            .to_key(K::Escape, None)
+            .build(),
+        // Not expressible with Manipulator::builder()
+        serde_json::from_value(serde_json::json!({
+            "type": "basic",
+            "from": {
+                "key_code": "spacebar"
+            },
+            "to": [
+                {
+                    "key_code": "spacebar"
+                }
+            ],
+            "to_if_held_down": [
+                {
+                    "key_code": "left_shift"
+                }
+            ]
+        }))
+        .unwrap(),
+    ]
+}
@@ -0,0 +1,42 @@
+//! `tests/fixtures/imported_every_builder_call.rs` is what `import` generates from the rule in the
+//! JSON file next to it. It is compiled here, so the generated code is checked to build, to
+//! reproduce the rule, and (through `cargo fmt --check`) to be formatted.
+
+use karaconf::{import, karabiner_data::Rule, merge::OWNED_RULE_PREFIX};
+
+/// Generated modules import `crate::karabiner_data`.
+mod karabiner_data {
+    pub use karaconf::karabiner_data::*;
+}
+
+#[path = "fixtures/imported_every_builder_call.rs"]
+mod imported_every_builder_call;
+
+fn rule() -> Rule {
+    serde_json::from_str(include_str!("fixtures/imported_every_builder_call.json")).unwrap()
+}
+
+#[test]
+fn generates_the_fixture_module() {
+    let rule_sets = import::rule_sets_from_rules(&[rule()], &[]);
+    assert_eq!(rule_sets.len(), 1);
+    assert_eq!(rule_sets[0].module_name, "imported_every_builder_call");
+    assert_eq!(rule_sets[0].description, "Imported: every builder call");
+    assert_eq!(
+        rule_sets[0].source,
+        include_str!("fixtures/imported_every_builder_call.rs")
+    );
+}
+
+#[test]
+fn fixture_module_reproduces_the_rule() {
+    let rule = rule();
+    assert_eq!(
+        imported_every_builder_call::DESCRIPTION,
+        rule.description.strip_prefix(OWNED_RULE_PREFIX).unwrap()
+    );
+    assert_eq!(
+        imported_every_builder_call::manipulators(),
+        rule.manipulators
+    );
+}
@@ -0,0 +1,106 @@
+use karaconf::{
+    backup,
+    config::Config,
+    install::{self, Installer},
+    profiles::{ProfileRules, ProfileTarget},
+    rule_sets,
+};
+
+/// An empty directory to use as the Karabiner configuration directory, holding `karabiner_json`.
+fn config_dir(name: &str, karabiner_json: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("karaconf-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("karabiner.json"), karabiner_json).unwrap();
+    dir
+}
+
+/// An installer of the first `rule_sets` rule sets of the registry into the first profile.
+fn installer(config_dir: &std::path::Path, rule_sets: usize) -> Installer {
+    let config = Config {
+        title: "Personal rules".to_string(),
+        profiles: vec![ProfileRules {
+            target: ProfileTarget::First,
+            rules: rule_sets::rules(&rule_sets::registry()[..rule_sets]),
+        }],
+    };
+    Installer {
+        config_dir: Some(config_dir.to_path_buf()),
+        ..Installer::new(config)
+    }
+}
+
+#[test]
+fn installs_after_restoring_a_backup() {
+    let dir = config_dir(
+        "restore",
+        r#"{"profiles": [{"name": "Default profile", "complex_modifications": {"rules": []}}]}"#,
+    );
+    install::install(&installer(&dir, 2)).unwrap();
+    install::install(&installer(&dir, 4)).unwrap();
+    let karabiner_json = dir.join("karabiner.json");
+
+    // The backup taken by the second install holds the two rules of the first one.
+    let backups = backup::list_backups(&karabiner_json).unwrap();
+    backup::restore(&karabiner_json, backups.last().unwrap(), 10).unwrap();
+    let restored: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(&karabiner_json).unwrap()).unwrap();
+    assert_eq!(
+        restored["profiles"][0]["complex_modifications"]["rules"]
+            .as_array()
+            .unwrap()
+            .len(),
+        2
+    );
+
+    let installed = install::install(&installer(&dir, 4)).unwrap();
+    assert!(installed.reports[0].drifted.is_empty());
+    assert!(installed.reports[0].deleted.is_empty());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn keeps_the_layout_of_karabiner_json_outside_the_rules() {
+    // As Karabiner-Elements writes it: 4 spaces, keys in its own order, a trailing newline.
+    let karabiner_json = r#"{
+    "global": {
+        "show_in_menu_bar": true
+    },
+    "profiles": [
+        {
+            "name": "Default profile",
+            "selected": true,
+            "complex_modifications": {
+                "rules": [],
+                "parameters": {
+                    "basic.to_if_alone_timeout_milliseconds": 1000
+                }
+            },
+            "virtual_hid_keyboard": {
+                "keyboard_type_v2": "ansi"
+            }
+        }
+    ]
+}
+"#;
+    let dir = config_dir("layout", karabiner_json);
+
+    let plan = install::plan(&installer(&dir, 2)).unwrap();
+
+    let written = String::from_utf8(plan.karabiner_json_data().unwrap()).unwrap();
+    assert!(written.ends_with("\n}\n"));
+    assert!(written.contains("\n                \"rules\": [\n                    {\n"));
+    let mut written_lines = written.lines();
+    for line in karabiner_json
+        .lines()
+        .filter(|line| !line.contains("\"rules\""))
+    {
+        assert!(
+            written_lines.any(|written| written == line),
+            "{:?} is missing or out of order in\n{}",
+            line,
+            written
+        );
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,36 @@
+use karaconf::json_style::JsonStyle;
+
+#[test]
+fn detects_the_indentation_and_trailing_newline() {
+    assert_eq!(
+        JsonStyle::detect("{\n  \"profiles\": []\n}\n"),
+        JsonStyle {
+            indent: "  ".to_string(),
+            trailing_newline: true,
+        }
+    );
+    assert_eq!(
+        JsonStyle::detect("{\n\t\"profiles\": [\n\t\t{}\n\t]\n}"),
+        JsonStyle {
+            indent: "\t".to_string(),
+            trailing_newline: false,
+        }
+    );
+}
+
+#[test]
+fn single_line_documents_use_the_defaults() {
+    assert_eq!(JsonStyle::detect(""), JsonStyle::default());
+    assert_eq!(
+        JsonStyle::detect("{\"profiles\": []}\n"),
+        JsonStyle::default()
+    );
+}
+
+#[test]
+fn writes_keys_in_the_order_they_were_read() {
+    let text = "{\n  \"profiles\": [],\n  \"global\": {\n    \"check_for_updates_on_startup\": true\n  }\n}\n";
+    let value: serde_json::Value = serde_json::from_str(text).unwrap();
+    let data = JsonStyle::detect(text).to_vec(&value).unwrap();
+    assert_eq!(String::from_utf8(data).unwrap(), text);
+}
@@ -0,0 +1,98 @@
+use karaconf::{
+    merge,
+    rule_sets::{self, Selection},
+};
+
+fn owned_rules() -> Vec<karaconf::karabiner_data::Rule> {
+    let rule_sets = rule_sets::select(rule_sets::registry(), &Selection::default()).unwrap();
+    rule_sets::rules(&rule_sets)
+}
+
+fn descriptions(rules: &[serde_json::Value]) -> Vec<&str> {
+    rules
+        .iter()
+        .map(|rule| rule["description"].as_str().unwrap())
+        .collect()
+}
+
+#[test]
+fn replaces_the_single_rule_installed_before_rule_sets() {
+    // karabiner.json as left by enabling the one "Personal rules" rule of custom.json in the UI.
+    let karabiner_json = serde_json::json!({
+        "profiles": [{
+            "name": "Default profile",
+            "complex_modifications": {
+                "rules": [
+                    {
+                        "description": "Personal rules",
+                        "manipulators": [{ "type": "basic", "from": { "key_code": "h" }, "to": [{ "key_code": "left_arrow" }] }]
+                    },
+                    { "description": "Gallery rule", "manipulators": [] }
+                ]
+            }
+        }]
+    });
+    let existing = karabiner_json["profiles"][0]["complex_modifications"]["rules"]
+        .as_array()
+        .unwrap();
+    let owned = owned_rules();
+
+    let merged = merge::merge_rules(existing, &owned).unwrap();
+
+    assert_eq!(merged.replaced, 0);
+    assert_eq!(merged.taken_over, vec!["Personal rules"]);
+    assert_eq!(merged.kept, vec!["Gallery rule"]);
+    let mut expected = owned
+        .iter()
+        .map(|rule| rule.description.as_str())
+        .collect::<Vec<_>>();
+    expected.push("Gallery rule");
+    assert_eq!(descriptions(&merged.rules), expected);
+}
+
+#[test]
+fn reports_unprefixed_rules_named_like_a_rule_set() {
+    let owned = owned_rules();
+    let mut existing = merge::merge_rules(&[], &owned).unwrap().rules;
+    let unprefixed = owned[1]
+        .description
+        .strip_prefix(merge::OWNED_RULE_PREFIX)
+        .unwrap();
+    existing.push(serde_json::json!({ "description": unprefixed, "manipulators": [] }));
+
+    let merged = merge::merge_rules(&existing, &owned).unwrap();
+
+    assert_eq!(merged.replaced, owned.len());
+    assert_eq!(merged.taken_over, vec![unprefixed]);
+    assert!(merged.kept.is_empty());
+}
+
+#[test]
+fn keeps_rules_turned_off_in_the_ui_off() {
+    let owned = owned_rules();
+    let mut installed = merge::merge_rules(&[], &owned).unwrap().rules;
+    let mut state = karaconf::drift::InstallState::default();
+    state.record("Default profile", &installed);
+    installed[0]["enabled"] = serde_json::json!(false);
+
+    let merged = merge::merge_rules(&installed, &owned).unwrap();
+
+    assert_eq!(merged.rules[0]["enabled"], serde_json::json!(false));
+    assert!(merged.rules[1].get("enabled").is_none());
+    assert!(state.drifted("Default profile", &installed).is_empty());
+}
+
+#[test]
+fn reports_rules_deleted_in_the_ui() {
+    let owned = owned_rules();
+    let mut installed = merge::merge_rules(&[], &owned).unwrap().rules;
+    let mut state = karaconf::drift::InstallState::default();
+    state.record("Default profile", &installed);
+
+    let deleted = installed.remove(0);
+
+    assert_eq!(
+        state.deleted("Default profile", &installed),
+        vec![deleted["description"].as_str().unwrap().to_string()]
+    );
+}
@@ -0,0 +1,76 @@
+use karaconf::{
+    Error,
+    profiles::{self, ProfileTarget},
+};
+
+fn karabiner_json() -> serde_json::Value {
+    serde_json::json!({
+        "profiles": [
+            { "name": "Default profile", "complex_modifications": { "rules": [{ "description": "a" }] } },
+            { "name": "Work" }
+        ]
+    })
+}
+
+fn path() -> &'static std::path::Path {
+    std::path::Path::new("karabiner.json")
+}
+
+#[test]
+fn finds_the_first_profile() {
+    let mut karabiner_json = karabiner_json();
+    let (complex_modifications, created) =
+        profiles::complex_modifications_mut(&mut karabiner_json, path(), &ProfileTarget::First)
+            .unwrap();
+    assert!(!created);
+    assert_eq!(
+        complex_modifications["rules"],
+        serde_json::json!([{ "description": "a" }])
+    );
+    assert_eq!(
+        profiles::profile_name(&karabiner_json, &ProfileTarget::First),
+        "Default profile"
+    );
+}
+
+#[test]
+fn finds_a_profile_by_name() {
+    let mut karabiner_json = karabiner_json();
+    let work = ProfileTarget::Named("Work".to_string());
+    let (complex_modifications, created) =
+        profiles::complex_modifications_mut(&mut karabiner_json, path(), &work).unwrap();
+    assert!(!created);
+    complex_modifications.insert("rules".to_string(), serde_json::json!([]));
+    assert_eq!(
+        karabiner_json["profiles"][1],
+        serde_json::json!({ "name": "Work", "complex_modifications": { "rules": [] } })
+    );
+    assert_eq!(karabiner_json["profiles"].as_array().unwrap().len(), 2);
+    assert_eq!(profiles::profile_name(&karabiner_json, &work), "Work");
+}
+
+#[test]
+fn creates_a_missing_profile_once() {
+    let mut karabiner_json = karabiner_json();
+    let gaming = ProfileTarget::Named("Gaming".to_string());
+    let (_, created) =
+        profiles::complex_modifications_mut(&mut karabiner_json, path(), &gaming).unwrap();
+    assert!(created);
+    assert_eq!(
+        karabiner_json["profiles"][2],
+        serde_json::json!({ "name": "Gaming", "complex_modifications": { "rules": [] } })
+    );
+
+    let (_, created) =
+        profiles::complex_modifications_mut(&mut karabiner_json, path(), &gaming).unwrap();
+    assert!(!created);
+    assert_eq!(karabiner_json["profiles"].as_array().unwrap().len(), 3);
+}
+
+#[test]
+fn the_first_profile_must_exist() {
+    let mut karabiner_json = serde_json::json!({ "profiles": [] });
+    let result =
+        profiles::complex_modifications_mut(&mut karabiner_json, path(), &ProfileTarget::First);
+    assert!(matches!(result, Err(Error::NoProfile(p)) if p == path()));
+}
@@ -0,0 +1,187 @@
+use karaconf::karabiner_data::ComplexModifications;
+
+fn assert_round_trip(json: &str) {
+    let original: serde_json::Value = serde_json::from_str(json).unwrap();
+    let parsed: ComplexModifications = serde_json::from_value(original.clone()).unwrap();
+    assert_eq!(serde_json::to_value(&parsed).unwrap(), original);
+}
+
+#[test]
+fn committed_custom_json_round_trips() {
+    assert_round_trip(include_str!("../custom.json"));
+}
+
+#[test]
+fn fields_karaconf_does_not_model_round_trip() {
+    assert_round_trip(
+        r#"{
+            "title": "Foreign rules",
+            "rules": [
+                {
+                    "description": "From the gallery",
+                    "available_since": "14.0.0",
+                    "manipulators": [
+                        {
+                            "type": "basic",
+                            "description": "caps lock to hyper",
+                            "conditions": [
+                                {"type": "device_if", "identifiers": [{"vendor_id": 1452}]},
+                                {"type": "frontmost_application_unless", "bundle_identifiers": ["^com\\.apple\\.Terminal$"]},
+                                {"type": "variable_if", "name": "hyper", "value": true}
+                            ],
+                            "from": {
+                                "key_code": "caps_lock",
+                                "modifiers": {"mandatory": ["left_shift"], "optional": ["any"]}
+                            },
+                            "to": [
+                                {"key_code": "left_shift", "modifiers": ["left_command", "left_control", "left_option"]},
+                                {"key_code": "a", "repeat": false},
+                                {"set_variable": {"name": "hyper", "value": 1, "key_up_value": 0}},
+                                {"mouse_key": {"speed_multiplier": 2.5}}
+                            ],
+                            "to_if_alone": [{"key_code": "escape"}],
+                            "to_if_held_down": [{"key_code": "spacebar"}],
+                            "parameters": {"basic.to_if_alone_timeout_milliseconds": 250}
+                        },
+                        {
+                            "type": "basic",
+                            "from": {"pointing_button": "button3"},
+                            "to": [{"shell_command": "open -a 'Mission Control.app'"}]
+                        },
+                        {
+                            "type": "basic",
+                            "from": {
+                                "simultaneous": [{"key_code": "j"}, {"key_code": "k"}],
+                                "simultaneous_options": {"key_down_order": "strict"}
+                            },
+                            "to": [{"key_code": "escape"}]
+                        }
+                    ]
+                }
+            ]
+        }"#,
+    );
+}
+
+#[test]
+fn events_and_types_karaconf_does_not_know_round_trip() {
+    assert_round_trip(
+        r#"{
+            "title": "Newer Karabiner-Elements",
+            "rules": [
+                {
+                    "description": "Keys and manipulator types added after karaconf",
+                    "manipulators": [
+                        {
+                            "type": "basic",
+                            "from": {"key_code": "f25", "modifiers": {"optional": ["any"]}},
+                            "to": [{"key_code": "escape"}]
+                        },
+                        {
+                            "type": "mouse_motion_to_scroll",
+                            "from": {"modifiers": {"mandatory": ["left_control"]}},
+                            "options": {"momentum_scroll_enabled": true}
+                        }
+                    ]
+                }
+            ]
+        }"#,
+    );
+}
+
+#[test]
+fn modifiers_karaconf_cannot_parse_round_trip() {
+    assert_round_trip(
+        r#"{
+            "title": "Hand-edited rules",
+            "rules": [
+                {
+                    "description": "Modifiers with fields and names karaconf does not know",
+                    "manipulators": [
+                        {
+                            "type": "basic",
+                            "from": {"key_code": "h", "modifiers": {"mandatory": ["left_control"], "comment": "hjkl"}},
+                            "to": [{"key_code": "left_arrow"}]
+                        },
+                        {
+                            "type": "basic",
+                            "from": {"key_code": "j", "modifiers": {"mandatory": ["hyper"]}},
+                            "to": [{"key_code": "down_arrow"}]
+                        }
+                    ]
+                }
+            ]
+        }"#,
+    );
+}
+
+#[test]
+fn alt_and_gui_modifier_names_round_trip() {
+    assert_round_trip(
+        r#"{
+            "title": "PC keyboard names",
+            "rules": [
+                {
+                    "description": "alt and gui are option and command",
+                    "manipulators": [
+                        {
+                            "type": "basic",
+                            "from": {
+                                "key_code": "a",
+                                "modifiers": {"mandatory": ["left_alt", "right_gui"], "optional": ["left_gui", "right_alt"]}
+                            },
+                            "to": [{"key_code": "b", "modifiers": ["left_alt"]}]
+                        }
+                    ]
+                }
+            ]
+        }"#,
+    );
+}
+
+#[test]
+fn single_items_are_read_as_lists_of_one() {
+    let single: ComplexModifications = serde_json::from_str(
+        r#"{
+            "title": "Single items",
+            "rules": [
+                {
+                    "description": "Karabiner accepts one item where it expects a list",
+                    "manipulators": [
+                        {
+                            "type": "basic",
+                            "from": {"key_code": "a", "modifiers": {"mandatory": "left_shift", "optional": "any"}},
+                            "to": {"key_code": "b"},
+                            "to_if_alone": {"key_code": "c"},
+                            "to_after_key_up": {"set_variable": {"name": "vk1", "value": 0}}
+                        }
+                    ]
+                }
+            ]
+        }"#,
+    )
+    .unwrap();
+    let lists: ComplexModifications = serde_json::from_str(
+        r#"{
+            "title": "Single items",
+            "rules": [
+                {
+                    "description": "Karabiner accepts one item where it expects a list",
+                    "manipulators": [
+                        {
+                            "type": "basic",
+                            "from": {"key_code": "a", "modifiers": {"mandatory": ["left_shift"], "optional": ["any"]}},
+                            "to": [{"key_code": "b"}],
+                            "to_if_alone": [{"key_code": "c"}],
+                            "to_after_key_up": [{"set_variable": {"name": "vk1", "value": 0}}]
+                        }
+                    ]
+                }
+            ]
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(single, lists);
+    assert!(single.rules[0].manipulators[0].extra.is_empty());
+    assert!(single.rules[0].manipulators[0].from.extra.is_empty());
+}
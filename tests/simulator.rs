@@ -0,0 +1,134 @@
+use karaconf::{
+    karabiner_data::{KeyCode as K, ModifierKey, To},
+    rule_sets::{self, Selection},
+    simulator::{KeyEvent::*, Output, Simulator},
+};
+
+fn simulator() -> Simulator {
+    let rule_sets = rule_sets::select(rule_sets::registry(), &Selection::default()).unwrap();
+    Simulator::from_rules(&rule_sets::rules(&rule_sets))
+}
+
+fn down(key_code: K, modifiers: Vec<ModifierKey>) -> Output {
+    Output::KeyDown {
+        key_code,
+        modifiers,
+    }
+}
+
+fn up(key_code: K) -> Output {
+    Output::KeyUp { key_code }
+}
+
+#[test]
+fn vk1_h_is_left_arrow() {
+    let mut simulator = simulator();
+    let outputs = simulator.run(&[
+        (0, Down(K::Lang1)),
+        (10, Down(K::H)),
+        (20, Up(K::H)),
+        (30, Up(K::Lang1)),
+    ]);
+    assert_eq!(outputs, vec![down(K::LeftArrow, vec![]), up(K::LeftArrow)]);
+    assert_eq!(simulator.variable("vk1"), 0);
+}
+
+#[test]
+fn vk1_is_set_while_kana_is_held() {
+    let mut simulator = simulator();
+    simulator.run(&[(0, Down(K::Lang1))]);
+    assert_eq!(simulator.variable("vk1"), 1);
+    simulator.run(&[(10, Up(K::Lang1))]);
+    assert_eq!(simulator.variable("vk1"), 0);
+}
+
+#[test]
+fn kana_alone_switches_input_source() {
+    let mut simulator = simulator();
+    let outputs = simulator.run(&[(0, Down(K::Lang1)), (100, Up(K::Lang1))]);
+    assert_eq!(
+        outputs,
+        vec![down(K::JapaneseKana, vec![]), up(K::JapaneseKana)]
+    );
+}
+
+#[test]
+fn kana_held_past_the_timeout_sends_nothing() {
+    let mut simulator = simulator();
+    let outputs = simulator.run(&[(0, Down(K::Lang1)), (1500, Up(K::Lang1))]);
+    assert_eq!(outputs, vec![]);
+}
+
+#[test]
+fn unmapped_keys_pass_through_with_modifiers() {
+    let mut simulator = simulator();
+    let outputs = simulator.run(&[
+        (0, Down(K::LeftShift)),
+        (10, Down(K::B)),
+        (20, Up(K::B)),
+        (30, Up(K::LeftShift)),
+    ]);
+    assert_eq!(
+        outputs,
+        vec![
+            down(K::LeftShift, vec![]),
+            down(K::B, vec![ModifierKey::LeftShift]),
+            up(K::B),
+            up(K::LeftShift),
+        ]
+    );
+}
+
+#[test]
+fn vk1_shift_y_copies_without_shift() {
+    let mut simulator = simulator();
+    let outputs = simulator.run(&[
+        (0, Down(K::Lang1)),
+        (10, Down(K::LeftShift)),
+        (20, Down(K::Y)),
+        (30, Up(K::Y)),
+    ]);
+    assert_eq!(outputs[0], down(K::LeftShift, vec![]));
+    assert_eq!(outputs[1], down(K::C, vec![ModifierKey::Cmd]));
+    assert_eq!(outputs[2], up(K::C));
+    assert!(matches!(outputs[3], Output::Event(To::Command { .. })));
+    assert_eq!(outputs.len(), 4);
+}
+
+#[test]
+fn app_specific_bindings_depend_on_the_frontmost_app() {
+    let events = [(0, Down(K::LeftCommand)), (10, Down(K::W)), (20, Up(K::W))];
+
+    let mut iterm2 = simulator().with_frontmost_application("com.googlecode.iterm2");
+    assert_eq!(iterm2.run(&events), vec![down(K::LeftCommand, vec![])]);
+
+    let mut elsewhere = simulator().with_frontmost_application("com.apple.Terminal");
+    assert_eq!(
+        elsewhere.run(&events),
+        vec![
+            down(K::LeftCommand, vec![]),
+            down(K::W, vec![ModifierKey::LeftCommand]),
+            up(K::W),
+        ]
+    );
+}
+
+#[test]
+fn earlier_to_keys_are_released_at_once() {
+    let mut simulator = simulator().with_frontmost_application("com.googlecode.iterm2");
+    let outputs = simulator.run(&[
+        (0, Down(K::Tab)),
+        (10, Down(K::C)),
+        (20, Up(K::C)),
+        (30, Up(K::Tab)),
+    ]);
+    assert_eq!(
+        outputs,
+        vec![
+            down(K::T, vec![ModifierKey::Ctrl]),
+            up(K::T),
+            down(K::C, vec![ModifierKey::Ctrl]),
+            up(K::C),
+        ]
+    );
+}
@@ -0,0 +1,91 @@
+//! Golden snapshots of the JSON each rule set generates, so that refactors of `karabiner_data` or
+//! the builder can be shown not to change the output. The whole output is compared with the
+//! committed custom.json. After an intended change, update them with
+//! `KARACONF_UPDATE_SNAPSHOTS=1 cargo test --test snapshots` and review the diff.
+
+use karaconf::{
+    config,
+    rule_sets::{self, Selection},
+};
+
+const SNAPSHOTS_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/snapshots");
+const CUSTOM_JSON: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/custom.json");
+const RULE_SETS_FILE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/rule_sets.json");
+
+fn updating() -> bool {
+    std::env::var_os("KARACONF_UPDATE_SNAPSHOTS").is_some_and(|v| !v.is_empty() && v != "0")
+}
+
+fn snapshot_path(name: &str) -> std::path::PathBuf {
+    std::path::Path::new(SNAPSHOTS_DIR).join(format!("{}.json", name))
+}
+
+/// Compares `value` with the snapshot `name`, or writes it in update mode. Returns the diff on mismatch.
+fn check_snapshot<T: serde::Serialize>(name: &str, value: &T) -> Option<String> {
+    let generated = serde_json::to_string_pretty(value).unwrap() + "\n";
+    check_file(&snapshot_path(name), &generated)
+}
+
+/// Compares `generated` with the file at `path`, or writes it in update mode. Returns the diff on mismatch.
+fn check_file(path: &std::path::Path, generated: &str) -> Option<String> {
+    if updating() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, generated).unwrap();
+        return None;
+    }
+    let committed = std::fs::read_to_string(path).unwrap_or_default();
+    let diff = karaconf::diff::text_diff(&committed, generated, path);
+    (!diff.is_empty()).then_some(diff)
+}
+
+fn assert_snapshots(mismatches: Vec<String>) {
+    assert!(
+        mismatches.is_empty(),
+        "{}\nsnapshots differ; if the change is intended, run `KARACONF_UPDATE_SNAPSHOTS=1 cargo test --test snapshots`",
+        mismatches.join("\n")
+    );
+}
+
+#[test]
+fn rule_set_manipulators_match_snapshots() {
+    let mismatches = rule_sets::registry()
+        .iter()
+        .filter_map(|rule_set| check_snapshot(rule_set.name(), &rule_set.manipulators()))
+        .collect();
+    assert_snapshots(mismatches);
+}
+
+#[test]
+fn complex_modifications_match_custom_json() {
+    let selection = Selection::load(std::path::Path::new(RULE_SETS_FILE)).unwrap();
+    let rule_sets = rule_sets::select(rule_sets::registry(), &selection).unwrap();
+    let complex_modifications = config::generate(&config::config(&rule_sets));
+    // As `install` and `generate <file>` write it, without a trailing newline.
+    let generated = serde_json::to_string_pretty(&complex_modifications).unwrap();
+    assert_snapshots(
+        check_file(std::path::Path::new(CUSTOM_JSON), &generated)
+            .into_iter()
+            .collect(),
+    );
+}
+
+#[test]
+fn every_snapshot_belongs_to_a_rule_set() {
+    let names = rule_sets::registry()
+        .iter()
+        .map(|rule_set| rule_set.name().to_string())
+        .collect::<Vec<_>>();
+    let mut stale = vec![];
+    for entry in std::fs::read_dir(SNAPSHOTS_DIR).into_iter().flatten() {
+        let path = entry.unwrap().path();
+        let name = path.file_stem().unwrap().to_string_lossy().to_string();
+        if !names.contains(&name) {
+            if updating() {
+                std::fs::remove_file(&path).unwrap();
+            } else {
+                stale.push(format!("{} has no rule set", path.display()));
+            }
+        }
+    }
+    assert_snapshots(stale);
+}
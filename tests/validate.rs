@@ -0,0 +1,138 @@
+use karaconf::{
+    karabiner_data::{KeyCode as K, Manipulator, MouseKey},
+    rule_sets,
+    validate::{self, Problem},
+};
+
+fn problems(manipulator: Manipulator) -> Vec<Problem> {
+    validate::validate(&[("test".to_string(), vec![manipulator])])
+        .into_iter()
+        .map(|invalid| invalid.problem)
+        .collect()
+}
+
+fn parse(json: serde_json::Value) -> Manipulator {
+    serde_json::from_value(json).unwrap()
+}
+
+#[test]
+fn rule_sets_have_no_problems() {
+    let rule_sets = rule_sets::registry()
+        .iter()
+        .map(|rule_set| (rule_set.name().to_string(), rule_set.manipulators()))
+        .collect::<Vec<_>>();
+    assert_eq!(validate::validate(&rule_sets), vec![]);
+}
+
+#[test]
+fn from_without_an_event_is_rejected() {
+    let manipulator = parse(serde_json::json!({
+        "type": "basic",
+        "from": {},
+        "to": [{ "key_code": "a" }]
+    }));
+    assert_eq!(problems(manipulator), vec![Problem::MissingFrom]);
+}
+
+#[test]
+fn a_manipulator_that_does_nothing_is_rejected() {
+    let manipulator = Manipulator::builder().from_key(K::A).build();
+    assert_eq!(problems(manipulator), vec![Problem::NoEffect]);
+
+    let manipulator = parse(serde_json::json!({
+        "type": "basic",
+        "from": { "key_code": "a" },
+        "to": [],
+        "to_if_alone": [],
+        "to_after_key_up": []
+    }));
+    assert_eq!(problems(manipulator), vec![Problem::NoEffect]);
+}
+
+#[test]
+fn to_if_alone_on_a_modifier_needs_to() {
+    let alone = Manipulator::builder()
+        .from_key(K::LeftCommand)
+        .to_if_alone(K::Lang2);
+    assert_eq!(
+        problems(alone.build()),
+        vec![Problem::ToIfAloneOnModifier {
+            key_code: "left_command".to_string()
+        }]
+    );
+
+    let held_and_alone = Manipulator::builder()
+        .from_key(K::LeftCommand)
+        .to_key(K::LeftCommand, None)
+        .to_if_alone(K::Lang2);
+    assert_eq!(problems(held_and_alone.build()), vec![]);
+}
+
+#[test]
+fn mouse_key_movement_must_fit_16_bits() {
+    let mouse = |x| {
+        Manipulator::builder()
+            .from_key(K::H)
+            .to_mouse(MouseKey {
+                x: Some(x),
+                y: None,
+                vertical_wheel: None,
+            })
+            .build()
+    };
+    assert_eq!(problems(mouse(i32::from(i16::MIN))), vec![]);
+    assert_eq!(
+        problems(mouse(i32::from(i16::MAX) + 1)),
+        vec![Problem::OutOfRange {
+            field: "to[0].mouse_key.x".to_string(),
+            value: serde_json::json!(32768),
+            expected: "an integer from -32768 to 32767".to_string(),
+        }]
+    );
+}
+
+#[test]
+fn timeouts_must_be_whole_milliseconds() {
+    let manipulator = parse(serde_json::json!({
+        "type": "basic",
+        "from": { "key_code": "a" },
+        "to": [{ "key_code": "b" }],
+        "parameters": {
+            "basic.to_if_alone_timeout_milliseconds": 500,
+            "basic.to_if_held_down_threshold_milliseconds": 0.5
+        }
+    }));
+    assert_eq!(
+        problems(manipulator),
+        vec![Problem::OutOfRange {
+            field: "parameters.basic.to_if_held_down_threshold_milliseconds".to_string(),
+            value: serde_json::json!(0.5),
+            expected: "a non-negative integer".to_string(),
+        }]
+    );
+}
+
+#[test]
+fn set_variable_needs_a_value_unless_it_unsets() {
+    let manipulator = |set_variable| {
+        parse(serde_json::json!({
+            "type": "basic",
+            "from": { "key_code": "a" },
+            "to": [{ "set_variable": set_variable }]
+        }))
+    };
+    assert_eq!(
+        problems(manipulator(
+            serde_json::json!({ "name": "vk1", "type": "unset" })
+        )),
+        vec![]
+    );
+    assert_eq!(
+        problems(manipulator(serde_json::json!({ "name": "vk1" }))),
+        vec![Problem::OutOfRange {
+            field: "to[0].set_variable.value".to_string(),
+            value: serde_json::Value::Null,
+            expected: "an integer, a boolean or a string".to_string(),
+        }]
+    );
+}